@@ -1,74 +1,299 @@
+use clap::ValueEnum;
+
+use crate::error::Error;
 use crate::layout::Qr;
-use std::iter;
+use crate::render::RenderOptions;
 
 const BMP_HEADER_LEN: usize = 54;
-const SCALE: usize = 4;
-
-pub fn qr_to_bitmap(qr: &Qr) -> Option<Vec<u8>> {
-    let mut res: Vec<Vec<bool>> = iter::repeat_n(
-        iter::repeat_n(false, (qr.data[0].len() + 8) * SCALE).collect(),
-        (qr.data.len() + 8) * SCALE,
-    )
-    .collect();
-    for (i, row) in res
-        .iter_mut()
-        .enumerate()
-        .take((qr.data[0].len() + 4) * SCALE)
-        .skip(4 * SCALE)
-    {
-        for (j, module) in row
-            .iter_mut()
-            .enumerate()
-            .take((qr.data.len() + 4) * SCALE)
-            .skip(4 * SCALE)
-        {
-            *module = qr.data[(i / SCALE) - 4][(j / SCALE) - 4];
-        }
-    }
-    make_bitmap(&res)
+
+/// Bits per pixel of a bmp
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, ValueEnum)]
+pub enum BmpDepth {
+    /// two colour table entries, the smallest files
+    #[default]
+    #[value(name = "1")]
+    One = 1,
+    /// a byte per pixel into the colour table, for programs that don't read 1 bit files
+    #[value(name = "8")]
+    Eight = 8,
+    /// colours stored in each pixel, the biggest files
+    #[value(name = "24")]
+    TwentyFour = 24,
+}
+
+pub fn qr_to_bitmap(qr: &Qr) -> Result<Vec<u8>, Error> {
+    qr_to_bitmap_with(qr, &RenderOptions::default(), BmpDepth::default())
 }
 
-pub fn make_bitmap(data: &[Vec<bool>]) -> Option<Vec<u8>> {
+/// the alpha channel of the colours is ignored
+pub fn qr_to_bitmap_with(
+    qr: &Qr,
+    options: &RenderOptions,
+    depth: BmpDepth,
+) -> Result<Vec<u8>, Error> {
+    make_bitmap_with(&options.pixels(qr), options, depth)
+}
+
+pub fn make_bitmap(data: &[Vec<bool>]) -> Result<Vec<u8>, Error> {
+    make_bitmap_with(data, &RenderOptions::default(), BmpDepth::default())
+}
+
+/// only the colours and dpi of `options` matter here, `data` is already scaled
+pub fn make_bitmap_with(
+    data: &[Vec<bool>],
+    options: &RenderOptions,
+    depth: BmpDepth,
+) -> Result<Vec<u8>, Error> {
     if data.is_empty() || data[0].is_empty() {
-        return None;
+        return Err(Error::EmptyImage);
     }
 
     let height = data.len();
     let width = data[0].len();
-    let pixel_length: usize = data.iter().map(|v| (v.len() * 3).next_multiple_of(4)).sum();
-    let result_length = BMP_HEADER_LEN + pixel_length;
+    let bpp = depth as usize;
+    let bgr = |[r, g, b, _]: [u8; 4]| [b, g, r];
+    let (foreground, background) = (bgr(options.foreground), bgr(options.background));
+    // light is the first entry and dark the second
+    let palette: Vec<u8> = match depth {
+        BmpDepth::TwentyFour => vec![],
+        _ => [background, foreground]
+            .iter()
+            .flat_map(|c| [c[0], c[1], c[2], 0])
+            .collect(),
+    };
+    // every row is padded to a multiple of 4 bytes
+    let stride = (bpp * width).div_ceil(32) * 4;
+    let pixel_offset = BMP_HEADER_LEN + palette.len();
+    let pixel_length = stride * height;
+    let result_length = pixel_offset + pixel_length;
     let mut res = vec![];
 
     // header
     res.extend_from_slice(b"BM"); // magic number
     res.extend_from_slice(&(result_length as u32).to_le_bytes()); // size
     res.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // reserved
-    res.extend_from_slice(&[0x36, 0x00, 0x00, 0x00]); // pixel array offset
+    res.extend_from_slice(&(pixel_offset as u32).to_le_bytes()); // pixel array offset
 
     // other header lol
     res.extend_from_slice(&[0x28, 0x00, 0x00, 0x00]); // DIB header size
     res.extend_from_slice(&(width as u32).to_le_bytes()); // width
     res.extend_from_slice(&(height as u32).to_le_bytes()); // height
     res.extend_from_slice(&[0x01, 0x00]); // planes
-    res.extend_from_slice(&[0x18, 0x00]); // bits per pixel
+    res.extend_from_slice(&(bpp as u16).to_le_bytes()); // bits per pixel
     res.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // compression
     res.extend_from_slice(&(pixel_length as u32).to_le_bytes()); // image size
-    res.extend_from_slice(&[0x23, 0x2E, 0x00, 0x00]); // x pixels per meter
-    res.extend_from_slice(&[0x23, 0x2E, 0x00, 0x00]); // y pixels per meter
-    res.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // colors in color table
+    res.extend_from_slice(&options.pixels_per_meter().to_le_bytes()); // x pixels per meter
+    res.extend_from_slice(&options.pixels_per_meter().to_le_bytes()); // y pixels per meter
+    res.extend_from_slice(&(palette.len() as u32 / 4).to_le_bytes()); // colors in color table
     res.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]); // important color count
+    res.extend_from_slice(&palette);
 
     // wheeeeee
-    let pixel_data: Vec<u8> = data
-        .iter()
-        .rev()
-        .map(|r| {
-            r.iter()
-                .flat_map(|b| if *b { [0, 0, 0] } else { [255, 255, 255] })
-        })
-        .flat_map(|r| r.chain(iter::repeat_n(0, width % 4)))
-        .collect();
-    res.extend_from_slice(&pixel_data);
-
-    Some(res)
+    for row in data.iter().rev() {
+        let start = res.len();
+        match depth {
+            BmpDepth::One => res.extend(row.chunks(8).map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |byte, (i, &dark)| byte | ((dark as u8) << (7 - i)))
+            })),
+            BmpDepth::Eight => res.extend(row.iter().map(|&dark| dark as u8)),
+            BmpDepth::TwentyFour => res.extend(
+                row.iter()
+                    .flat_map(|&dark| if dark { foreground } else { background }),
+            ),
+        }
+        res.resize(start + stride, 0);
+    }
+
+    Ok(res)
+}
+
+/// reads an uncompressed 1, 8, 24 or 32 bit bmp into rows of luminance values, top row first
+pub fn read_bitmap(bytes: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    if bytes.len() < BMP_HEADER_LEN || &bytes[..2] != b"BM" {
+        return Err(Error::InvalidImage);
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+    let offset = u32_at(10) as usize;
+    let dib_len = u32_at(14) as usize;
+    let width = u32_at(18) as i32;
+    let height = u32_at(22) as i32;
+    let bpp = u16_at(28) as usize;
+    let compression = u32_at(30);
+    let colors = u32_at(46) as usize;
+
+    // 32 bit files often say bitfields even with the usual channel order
+    if dib_len < 40 || !matches!((bpp, compression), (1 | 8 | 24, 0) | (32, 0 | 3)) {
+        return Err(Error::InvalidImage);
+    }
+    if width <= 0 || height == 0 {
+        return Err(Error::EmptyImage);
+    }
+    let (width, rows) = (width as usize, height.unsigned_abs() as usize);
+
+    let palette: Vec<u8> = if bpp <= 8 {
+        let count = if colors == 0 { 1 << bpp } else { colors };
+        let start = 14 + dib_len;
+        bytes
+            .get(start..start + count * 4)
+            .ok_or(Error::InvalidImage)?
+            .chunks(4)
+            .map(|c| luminance(c[2], c[1], c[0]))
+            .collect()
+    } else {
+        vec![]
+    };
+
+    let stride = (bpp * width).div_ceil(32) * 4;
+    let mut res = vec![];
+    for y in 0..rows {
+        let row = bytes
+            .get(offset + y * stride..offset + (y + 1) * stride)
+            .ok_or(Error::InvalidImage)?;
+        let pixels = (0..width).map(|x| match bpp {
+            1 => palette
+                .get(((row[x / 8] >> (7 - x % 8)) & 1) as usize)
+                .copied(),
+            8 => palette.get(row[x] as usize).copied(),
+            _ => {
+                let i = x * bpp / 8;
+                Some(luminance(row[i + 2], row[i + 1], row[i]))
+            }
+        });
+        res.push(
+            pixels
+                .collect::<Option<Vec<u8>>>()
+                .ok_or(Error::InvalidImage)?,
+        );
+    }
+
+    // positive height means the bottom row comes first
+    if height > 0 {
+        res.reverse();
+    }
+    Ok(res)
+}
+
+pub(crate) fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // headers for a 3x2 image, most fields don't matter to the reader
+    fn header(bpp: u16, height: i32, palette: &[[u8; 4]], pixels: &[u8]) -> Vec<u8> {
+        let offset = 54 + palette.len() * 4;
+        let mut res = vec![];
+        res.extend_from_slice(b"BM");
+        res.extend_from_slice(&((offset + pixels.len()) as u32).to_le_bytes());
+        res.extend_from_slice(&[0; 4]);
+        res.extend_from_slice(&(offset as u32).to_le_bytes());
+        res.extend_from_slice(&40u32.to_le_bytes());
+        res.extend_from_slice(&3u32.to_le_bytes());
+        res.extend_from_slice(&height.to_le_bytes());
+        res.extend_from_slice(&1u16.to_le_bytes());
+        res.extend_from_slice(&bpp.to_le_bytes());
+        res.extend_from_slice(&[0; 16]);
+        res.extend_from_slice(&(palette.len() as u32).to_le_bytes());
+        res.extend_from_slice(&[0; 4]);
+        res.extend(palette.iter().flatten());
+        res.extend_from_slice(pixels);
+        res
+    }
+
+    #[test]
+    fn test_read_bitmap_24() {
+        let data = vec![vec![true, false, true], vec![false, false, true]];
+        let bmp = make_bitmap_with(&data, &RenderOptions::default(), BmpDepth::TwentyFour);
+        let res = read_bitmap(&bmp.unwrap()).unwrap();
+        assert_eq!(res, vec![vec![0, 255, 0], vec![255, 255, 0]]);
+    }
+
+    #[test]
+    fn test_read_bitmap_palette() {
+        let palette = [[255, 255, 255, 0], [0, 0, 0, 0]];
+        // bottom row first, each row padded to 4 bytes
+        let bmp = header(
+            1,
+            2,
+            &palette,
+            &[0b0010_0000, 0, 0, 0, 0b1010_0000, 0, 0, 0],
+        );
+        assert_eq!(
+            read_bitmap(&bmp),
+            Ok(vec![vec![0, 255, 0], vec![255, 255, 0]])
+        );
+
+        let palette = [[0, 0, 0, 0], [255, 255, 255, 0], [0, 0, 255, 0]];
+        let bmp = header(8, -2, &palette, &[0, 1, 2, 0, 2, 1, 0, 0]);
+        assert_eq!(
+            read_bitmap(&bmp),
+            Ok(vec![vec![0, 255, 76], vec![76, 255, 0]])
+        );
+
+        let bmp = header(8, -2, &palette, &[0, 1, 3, 0, 2, 1, 0, 0]);
+        assert_eq!(read_bitmap(&bmp), Err(Error::InvalidImage));
+    }
+
+    #[test]
+    fn test_read_bitmap_32() {
+        let pixels = [[0, 0, 0, 255], [255, 255, 255, 255], [0, 255, 0, 255]].concat();
+        let bmp = header(32, -1, &[], &pixels);
+        assert_eq!(read_bitmap(&bmp), Ok(vec![vec![0, 255, 149]]));
+
+        assert_eq!(read_bitmap(&bmp[..60]), Err(Error::InvalidImage));
+        assert_eq!(read_bitmap(b"GIF89a"), Err(Error::InvalidImage));
+    }
+
+    #[test]
+    fn test_bitmap_depths() {
+        // odd widths need padding in every mode
+        for width in 1..=33usize {
+            let data: Vec<Vec<bool>> = (0..3)
+                .map(|y| (0..width).map(|x| (x * 7 + y) % 3 == 0).collect())
+                .collect();
+            let expected: Vec<Vec<u8>> = data
+                .iter()
+                .map(|r| r.iter().map(|&dark| if dark { 0 } else { 255 }).collect())
+                .collect();
+            for (depth, row_bytes) in [
+                (BmpDepth::One, width.div_ceil(8)),
+                (BmpDepth::Eight, width),
+                (BmpDepth::TwentyFour, width * 3),
+            ] {
+                let bmp = make_bitmap_with(&data, &RenderOptions::default(), depth).unwrap();
+                let palette = if depth == BmpDepth::TwentyFour { 0 } else { 8 };
+                assert_eq!(
+                    bmp.len(),
+                    BMP_HEADER_LEN + palette + row_bytes.next_multiple_of(4) * 3
+                );
+                assert_eq!(read_bitmap(&bmp).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bitmap_colours() {
+        let options = RenderOptions {
+            foreground: [0x12, 0x34, 0x56, 255],
+            background: [0xAA, 0xBB, 0xCC, 0],
+            dpi: 600,
+            ..Default::default()
+        };
+        let bmp = make_bitmap_with(&[vec![true, false]], &options, BmpDepth::TwentyFour).unwrap();
+        assert_eq!(&bmp[38..46], &[0x46, 0x5C, 0, 0, 0x46, 0x5C, 0, 0]);
+        assert_eq!(&bmp[54..], &[0x56, 0x34, 0x12, 0xCC, 0xBB, 0xAA, 0, 0]);
+
+        // light then dark in the colour table
+        let bmp = make_bitmap_with(&[vec![true, false]], &options, BmpDepth::Eight).unwrap();
+        assert_eq!(
+            &bmp[54..],
+            &[0xCC, 0xBB, 0xAA, 0, 0x56, 0x34, 0x12, 0, 1, 0, 0, 0]
+        );
+    }
 }
@@ -71,6 +71,11 @@ impl Bitstream {
         self.data.len().div_ceil(8)
     }
 
+    /// length in bits
+    pub fn bit_len(&self) -> usize {
+        self.data.len()
+    }
+
     /// how many bits free in current byte
     pub fn free_bits(&self) -> usize {
         self.data.len().next_multiple_of(8) - self.data.len()
@@ -83,6 +88,41 @@ impl From<Bitstream> for Vec<bool> {
     }
 }
 
+/// Reads bits back out of bytes, most significant bit first
+#[derive(Debug)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// bits left to read
+    pub fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    /// None if there aren't enough bits left
+    pub fn read(&mut self, len: usize) -> Option<u32> {
+        if len > 32 {
+            panic!("can't read {} bits into a u32!", len);
+        }
+        if len > self.remaining() {
+            return None;
+        }
+        let mut res = 0;
+        for _ in 0..len {
+            let bit = (self.data[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+            res = (res << 1) | bit as u32;
+            self.pos += 1;
+        }
+        Some(res)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,4 +169,20 @@ mod tests {
         b.push_u32(0x00000005, 1);
         assert_eq!(b.as_bytes(), vec![0xAB, 0xCD, 0xEF, 0x12, 0x80])
     }
+
+    #[test]
+    fn test_bit_reader() {
+        let mut b = Bitstream::new();
+        b.push_u16(0x1ABC, 13);
+        b.push_u8(0x5, 3);
+        b.push_u32(0xDEADBEEF, 32);
+        let bytes = b.as_bytes();
+        let mut r = BitReader::new(&bytes);
+        assert_eq!(r.read(13), Some(0x1ABC));
+        assert_eq!(r.read(3), Some(0x5));
+        assert_eq!(r.remaining(), 32);
+        assert_eq!(r.read(33 - 1), Some(0xDEADBEEF));
+        assert_eq!(r.read(1), None);
+        assert_eq!(r.read(0), Some(0));
+    }
 }
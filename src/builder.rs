@@ -0,0 +1,549 @@
+use crate::encoding::{self, ECLevel, Fnc1, GS, Mode, Segment};
+use crate::error::Error;
+use crate::layout::{DEFAULT_QUIET_ZONE, EncodeReport, Qr};
+
+#[derive(Debug, Clone)]
+enum Payload {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+/// Collects the options for making a qr code
+#[derive(Debug, Clone)]
+pub struct QrBuilder {
+    payload: Payload,
+    ec: ECLevel,
+    mask: Option<usize>,
+    min_version: usize,
+    max_version: usize,
+    mode: Option<Mode>,
+    eci: Option<u32>,
+    utf8_eci: bool,
+    fnc1: Option<Fnc1>,
+    quiet_zone: usize,
+    boost_ec: bool,
+    fit: bool,
+    verify: bool,
+}
+
+impl QrBuilder {
+    pub fn new(data: &str) -> Self {
+        Self::with_payload(Payload::Text(data.to_string()))
+    }
+
+    /// raw bytes, always encoded in byte mode
+    pub fn from_bytes(data: &[u8]) -> Self {
+        Self::with_payload(Payload::Bytes(data.to_vec()))
+    }
+
+    fn with_payload(payload: Payload) -> Self {
+        Self {
+            payload,
+            ec: ECLevel::Low,
+            mask: None,
+            min_version: 1,
+            max_version: 40,
+            mode: None,
+            eci: None,
+            utf8_eci: false,
+            fnc1: None,
+            quiet_zone: DEFAULT_QUIET_ZONE,
+            boost_ec: false,
+            fit: false,
+            verify: false,
+        }
+    }
+
+    pub fn ec(mut self, ec: ECLevel) -> Self {
+        self.ec = ec;
+        self
+    }
+
+    /// force a mask pattern instead of picking the one with the lowest penalty score
+    pub fn mask(mut self, mask: usize) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    pub fn min_version(mut self, version: usize) -> Self {
+        self.min_version = version;
+        self
+    }
+
+    /// fail with `DataTooLongForVersion` instead of going past this version
+    pub fn max_version(mut self, version: usize) -> Self {
+        self.max_version = version;
+        self
+    }
+
+    /// only use this version, same as setting the min and max to it
+    pub fn version(self, version: usize) -> Self {
+        self.min_version(version).max_version(version)
+    }
+
+    /// encode everything in one mode instead of picking the shortest mix of modes
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// declare the character set with an eci segment at the start
+    pub fn eci(mut self, assignment: u32) -> Self {
+        self.eci = Some(assignment);
+        self
+    }
+
+    /// declare UTF-8 if there's any non ascii byte mode data, ignored if an eci is set
+    pub fn utf8_eci(mut self, utf8_eci: bool) -> Self {
+        self.utf8_eci = utf8_eci;
+        self
+    }
+
+    /// mark the data as GS1 or industry specific data
+    pub fn fnc1(mut self, fnc1: Fnc1) -> Self {
+        self.fnc1 = Some(fnc1);
+        self
+    }
+
+    /// width of the blank border in modules, renderers use this
+    pub fn quiet_zone(mut self, modules: usize) -> Self {
+        self.quiet_zone = modules;
+        self
+    }
+
+    /// raise the ec level as far as it goes without needing a bigger version
+    pub fn boost_ec(mut self, boost_ec: bool) -> Self {
+        self.boost_ec = boost_ec;
+        self
+    }
+
+    /// use the highest ec level that fits within the max version, even if a lower one would fit
+    /// in a smaller version
+    pub fn fit(mut self, fit: bool) -> Self {
+        self.fit = fit;
+        self
+    }
+
+    /// decode every symbol after making it and fail with `VerifyFailed` if it doesn't match
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    pub fn build(&self) -> Result<(Qr, EncodeReport), Error> {
+        let levels = match self.fit {
+            true => ECLevel::ASCENDING.iter().rev().copied().collect(),
+            false => vec![self.ec],
+        };
+        for ec in levels.into_iter().filter(|&level| level >= self.ec) {
+            match encoding::detect_version_with(
+                self.min_version..=self.max_version,
+                ec,
+                |version| self.segments_for(version),
+            ) {
+                Ok((version, segments)) => return self.finish(&segments, version, ec),
+                Err(Error::DataTooLong | Error::DataTooLongForVersion(..)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        match self.max_version {
+            40 => Err(Error::DataTooLong),
+            v => Err(Error::DataTooLongForVersion(v, self.ec)),
+        }
+    }
+
+    /// like `build` but splits data that doesn't fit in `max_version` across up to 16 codes with
+    /// structured append
+    pub fn build_split(&self) -> Result<Vec<(Qr, EncodeReport)>, Error> {
+        match self.build() {
+            Err(Error::DataTooLong | Error::DataTooLongForVersion(..)) => (),
+            res => return res.map(|r| vec![r]),
+        }
+        let segments = self.segments_for(self.max_version)?;
+        encoding::structured_append(&segments, self.max_version, self.ec)?
+            .iter()
+            .map(|part| {
+                let (version, _) = encoding::detect_version_with(
+                    self.min_version..=self.max_version,
+                    self.ec,
+                    |_| Ok(part.clone()),
+                )?;
+                self.finish(part, version, self.ec)
+            })
+            .collect()
+    }
+
+    fn finish(
+        &self,
+        segments: &[Segment],
+        version: usize,
+        mut ec: ECLevel,
+    ) -> Result<(Qr, EncodeReport), Error> {
+        if self.boost_ec {
+            for level in ECLevel::ASCENDING {
+                if level > ec && encoding::fits(segments, version, level) {
+                    ec = level;
+                }
+            }
+        }
+        let (mut qr, mut report) = Qr::from_segments(segments, version, ec, self.mask)?;
+        qr.set_quiet_zone(self.quiet_zone);
+        report.requested_ec = self.ec;
+        if self.verify {
+            match qr.decode() {
+                Ok(decoded) if decoded.segments == segments => (),
+                _ => return Err(Error::VerifyFailed),
+            }
+        }
+        Ok((qr, report))
+    }
+
+    /// the segments to use for versions with the same length indicator sizes as this one
+    fn segments_for(&self, version: usize) -> Result<Vec<Segment>, Error> {
+        let mut res = match (&self.payload, self.mode) {
+            (Payload::Bytes(data), None | Some(Mode::Byte)) => vec![Segment::bytes(data)],
+            (Payload::Bytes(_), Some(mode)) => return Err(Error::InvalidMode(mode)),
+            (Payload::Text(data), Some(Mode::Alphanumeric)) if self.fnc1.is_some() => {
+                let data = data.replace('%', "%%").replace(GS, "%");
+                vec![Segment::new(&data, Mode::Alphanumeric)?]
+            }
+            (Payload::Text(data), Some(mode)) => vec![Segment::new(data, mode)?],
+            (Payload::Text(data), None) if self.fnc1.is_some() => {
+                encoding::segment_fnc1(data, version)
+            }
+            (Payload::Text(data), None) => encoding::segment(data, version),
+        };
+        if let Some(fnc1) = self.fnc1 {
+            res.insert(0, fnc1.segment()?);
+        }
+        match self.eci {
+            Some(assignment) => res.insert(0, Segment::eci(assignment)?),
+            None if self.utf8_eci => encoding::insert_utf8_eci(&mut res),
+            None => (),
+        }
+        Ok(res)
+    }
+}
+
+// the positional constructors from before the builder, kept so existing callers still build
+impl Qr {
+    #[deprecated(note = "use QrBuilder, which has a setter for each of these")]
+    pub fn make_qr(
+        data: &str,
+        ec: Option<ECLevel>,
+        mask: Option<usize>,
+        min_version: Option<usize>,
+        utf8_eci: bool,
+        fnc1: Option<Fnc1>,
+    ) -> Result<(Self, EncodeReport), Error> {
+        Self::text_builder(data, ec, mask, min_version, utf8_eci, fnc1).build()
+    }
+
+    /// makes a qr code holding raw bytes in a single byte mode segment
+    #[deprecated(note = "use QrBuilder, which has a setter for each of these")]
+    pub fn make_qr_bytes(
+        data: &[u8],
+        ec: Option<ECLevel>,
+        mask: Option<usize>,
+        min_version: Option<usize>,
+    ) -> Result<(Self, EncodeReport), Error> {
+        Self::bytes_builder(data, ec, mask, min_version).build()
+    }
+
+    /// makes a single qr code if the data fits, otherwise splits it across up to 16 codes with
+    /// structured append
+    #[deprecated(note = "use QrBuilder, which has a setter for each of these")]
+    pub fn make_qr_split(
+        data: &str,
+        ec: Option<ECLevel>,
+        mask: Option<usize>,
+        min_version: Option<usize>,
+        utf8_eci: bool,
+        fnc1: Option<Fnc1>,
+    ) -> Result<Vec<(Self, EncodeReport)>, Error> {
+        Self::text_builder(data, ec, mask, min_version, utf8_eci, fnc1).build_split()
+    }
+
+    /// raw bytes version of `make_qr_split`
+    #[deprecated(note = "use QrBuilder, which has a setter for each of these")]
+    pub fn make_qr_bytes_split(
+        data: &[u8],
+        ec: Option<ECLevel>,
+        mask: Option<usize>,
+        min_version: Option<usize>,
+    ) -> Result<Vec<(Self, EncodeReport)>, Error> {
+        Self::bytes_builder(data, ec, mask, min_version).build_split()
+    }
+
+    fn text_builder(
+        data: &str,
+        ec: Option<ECLevel>,
+        mask: Option<usize>,
+        min_version: Option<usize>,
+        utf8_eci: bool,
+        fnc1: Option<Fnc1>,
+    ) -> QrBuilder {
+        let mut res = Self::with_options(QrBuilder::new(data), ec, mask, min_version);
+        res = res.utf8_eci(utf8_eci);
+        if let Some(fnc1) = fnc1 {
+            res = res.fnc1(fnc1);
+        }
+        res
+    }
+
+    fn bytes_builder(
+        data: &[u8],
+        ec: Option<ECLevel>,
+        mask: Option<usize>,
+        min_version: Option<usize>,
+    ) -> QrBuilder {
+        Self::with_options(QrBuilder::from_bytes(data), ec, mask, min_version)
+    }
+
+    fn with_options(
+        mut builder: QrBuilder,
+        ec: Option<ECLevel>,
+        mask: Option<usize>,
+        min_version: Option<usize>,
+    ) -> QrBuilder {
+        if let Some(ec) = ec {
+            builder = builder.ec(ec);
+        }
+        if let Some(mask) = mask {
+            builder = builder.mask(mask);
+        }
+        if let Some(min_version) = min_version {
+            builder = builder.min_version(min_version);
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let (qr, report) = QrBuilder::new("HELLO WORLD")
+            .ec(ECLevel::Quartile)
+            .mask(3)
+            .min_version(2)
+            .quiet_zone(2)
+            .build()
+            .unwrap();
+        assert_eq!(qr.version(), 2);
+        assert_eq!(qr.ec(), ECLevel::Quartile);
+        assert_eq!(qr.mask(), Some(3));
+        assert_eq!(qr.quiet_zone(), 2);
+        assert_eq!(report.modes, vec![Mode::Alphanumeric]);
+    }
+
+    #[test]
+    fn test_build_options() {
+        let (qr, _) = QrBuilder::new("12345").mode(Mode::Byte).build().unwrap();
+        assert_eq!(qr.segments()[0].mode, Mode::Byte);
+
+        let (qr, _) = QrBuilder::new("abc").eci(3).build().unwrap();
+        assert_eq!(qr.segments()[0], Segment::eci(3).unwrap());
+
+        let (qr, _) = QrBuilder::new("10ABC%1")
+            .fnc1(Fnc1::First)
+            .mode(Mode::Alphanumeric)
+            .build()
+            .unwrap();
+        assert_eq!(qr.segments()[1].data, b"10ABC%%1");
+        assert_eq!(
+            QrBuilder::new("1234")
+                .fnc1(Fnc1::Second(150))
+                .build()
+                .unwrap_err(),
+            Error::InvalidApplicationIndicator(150)
+        );
+
+        assert_eq!(
+            QrBuilder::from_bytes(b"abc")
+                .mode(Mode::Numeric)
+                .build()
+                .unwrap_err(),
+            Error::InvalidMode(Mode::Numeric)
+        );
+        assert_eq!(
+            QrBuilder::new("abc")
+                .mode(Mode::Numeric)
+                .build()
+                .unwrap_err(),
+            Error::UnencodableCharacter('a')
+        );
+        assert_eq!(
+            QrBuilder::new(&"a".repeat(100))
+                .max_version(3)
+                .build()
+                .unwrap_err(),
+            Error::DataTooLongForVersion(3, ECLevel::Low)
+        );
+        assert_eq!(
+            QrBuilder::new(&"a".repeat(8000)).build().unwrap_err(),
+            Error::DataTooLong
+        );
+        assert_eq!(
+            QrBuilder::new("a").min_version(0).build().unwrap_err(),
+            Error::InvalidVersion(0)
+        );
+        let backwards = QrBuilder::new("a").min_version(5).max_version(3);
+        assert_eq!(
+            backwards.build().unwrap_err(),
+            Error::InvalidVersionRange(5, 3)
+        );
+        assert_eq!(
+            backwards.build_split().unwrap_err(),
+            Error::InvalidVersionRange(5, 3)
+        );
+    }
+
+    #[test]
+    fn test_boost_ec() {
+        // 100 bits fits at version 1 up to quartile
+        let (qr, report) = QrBuilder::new("hello world")
+            .boost_ec(true)
+            .build()
+            .unwrap();
+        assert_eq!(qr.version(), 1);
+        assert_eq!(qr.ec(), ECLevel::Quartile);
+        assert_eq!(report.ec, ECLevel::Quartile);
+        assert_eq!(report.requested_ec, ECLevel::Low);
+
+        let (qr, _) = QrBuilder::new("hello world").build().unwrap();
+        assert_eq!(qr.ec(), ECLevel::Low);
+
+        // never grows the symbol or lowers the ec level
+        for len in (1..400).step_by(7) {
+            let data = "a".repeat(len);
+            let (plain, _) = QrBuilder::new(&data).ec(ECLevel::Medium).build().unwrap();
+            let (boosted, _) = QrBuilder::new(&data)
+                .ec(ECLevel::Medium)
+                .boost_ec(true)
+                .build()
+                .unwrap();
+            assert_eq!(plain.version(), boosted.version());
+            assert!(boosted.ec() >= ECLevel::Medium);
+        }
+    }
+
+    #[test]
+    fn test_version_constraints() {
+        let (qr, _) = QrBuilder::new("a").version(7).build().unwrap();
+        assert_eq!(qr.version(), 7);
+        assert_eq!(
+            QrBuilder::new(&"a".repeat(30))
+                .ec(ECLevel::High)
+                .version(1)
+                .build()
+                .unwrap_err(),
+            Error::DataTooLongForVersion(1, ECLevel::High)
+        );
+
+        // low fits in version 3, but high fits in version 5
+        let data = "a".repeat(40);
+        let (qr, _) = QrBuilder::new(&data).max_version(5).build().unwrap();
+        assert_eq!((qr.version(), qr.ec()), (3, ECLevel::Low));
+        let (qr, report) = QrBuilder::new(&data)
+            .max_version(5)
+            .fit(true)
+            .build()
+            .unwrap();
+        assert_eq!((qr.version(), qr.ec()), (5, ECLevel::High));
+        assert_eq!(report.requested_ec, ECLevel::Low);
+
+        // fit never goes below the requested level
+        assert_eq!(
+            QrBuilder::new(&data)
+                .ec(ECLevel::High)
+                .max_version(3)
+                .fit(true)
+                .build()
+                .unwrap_err(),
+            Error::DataTooLongForVersion(3, ECLevel::High)
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let (qr, _) = QrBuilder::new("ｱｲｳ茗荷 12345 HELLO")
+            .verify(true)
+            .build()
+            .unwrap();
+        assert_eq!(qr.decode().unwrap().text(), "ｱｲｳ茗荷 12345 HELLO");
+    }
+
+    #[test]
+    fn test_build_split() {
+        let codes = QrBuilder::from_bytes(&[0x55; 500])
+            .max_version(10)
+            .build_split()
+            .unwrap();
+        assert_eq!(codes.len(), 2);
+        assert!(codes.iter().all(|(qr, _)| qr.version() <= 10));
+        assert_eq!(codes[0].0.segments()[0].mode, Mode::StructuredAppend);
+
+        // more characters than the length indicator can count below version 10
+        let digits = "7".repeat(2000);
+        for (builder, data) in [
+            (
+                QrBuilder::from_bytes(&[0x55; 300]).max_version(9),
+                &[0x55; 300][..],
+            ),
+            (QrBuilder::new(&digits).max_version(5), digits.as_bytes()),
+        ] {
+            let codes = builder.build_split().unwrap();
+            assert!(codes.len() > 1);
+            let joined: Vec<u8> = codes
+                .iter()
+                .flat_map(|(qr, _)| qr.decode().unwrap().content())
+                .collect();
+            assert_eq!(joined, data);
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_make_qr_bytes() {
+        let text = Qr::make_qr(
+            "hello world",
+            Some(ECLevel::Medium),
+            Some(2),
+            None,
+            false,
+            None,
+        )
+        .unwrap();
+        let bytes =
+            Qr::make_qr_bytes(b"hello world", Some(ECLevel::Medium), Some(2), None).unwrap();
+        assert_eq!(text.0.data, bytes.0.data);
+        assert_eq!(text.1, bytes.1);
+        assert!(Qr::make_qr_bytes(&[0xFF, 0x00, 0x80], None, None, None).is_ok());
+        assert_eq!(
+            Qr::make_qr_bytes(&[0x55; 5000], None, None, None).unwrap_err(),
+            Error::DataTooLong
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_make_qr_split() {
+        assert_eq!(
+            Qr::make_qr_split("hello", None, None, None, false, None)
+                .unwrap()
+                .len(),
+            1
+        );
+        let codes = Qr::make_qr_bytes_split(&[0x55; 5000], None, None, None).unwrap();
+        assert_eq!(codes.len(), 2);
+        assert_eq!(codes[0].1.version, 40);
+        assert_eq!(codes[0].1.modes[0], Mode::StructuredAppend);
+        assert_eq!(
+            Qr::make_qr_bytes_split(&[0x55; 50000], None, None, None).unwrap_err(),
+            Error::TooManySymbols(17)
+        );
+    }
+}
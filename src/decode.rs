@@ -0,0 +1,301 @@
+use crate::encoding::{self, ECLevel, Mode, Segment};
+use crate::error::Error;
+use crate::layout::{self, Qr};
+use crate::rsec;
+use crate::scan;
+
+/// What was read out of a symbol
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Decoded {
+    pub version: usize,
+    pub ec: ECLevel,
+    pub mask: usize,
+    pub segments: Vec<Segment>,
+    /// how many codewords reed solomon had to fix
+    pub errors_corrected: usize,
+    /// how many bits of the format information were wrong
+    pub format_errors: u32,
+}
+
+impl Decoded {
+    /// the message with kanji turned into UTF-8 and fnc1 escapes undone, header segments are
+    /// left out
+    pub fn content(&self) -> Vec<u8> {
+        let fnc1 = self
+            .segments
+            .iter()
+            .any(|s| matches!(s.mode, Mode::Fnc1First | Mode::Fnc1Second));
+        let mut res = vec![];
+        for segment in &self.segments {
+            match segment.mode {
+                Mode::Alphanumeric if fnc1 => res.extend(encoding::unescape_fnc1(&segment.data)),
+                Mode::Kanji => {
+                    for pair in segment.data.chunks_exact(2) {
+                        let c = encoding::sjis_to_kanji(u16::from_be_bytes([pair[0], pair[1]]))
+                            .and_then(encoding::kanji_to_char)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER);
+                        res.extend(c.encode_utf8(&mut [0; 4]).bytes());
+                    }
+                }
+                Mode::Numeric | Mode::Alphanumeric | Mode::Byte => res.extend(&segment.data),
+                _ => (),
+            }
+        }
+        res
+    }
+
+    /// `content` as a string, invalid UTF-8 is replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.content()).into_owned()
+    }
+
+    /// the index, total and parity if this is part of a structured append sequence
+    pub fn structured_append(&self) -> Option<(usize, usize, u8)> {
+        self.segments
+            .iter()
+            .find(|s| s.mode == Mode::StructuredAppend)
+            .map(|s| {
+                (
+                    (s.data[0] >> 4) as usize,
+                    (s.data[0] & 0xF) as usize + 1,
+                    s.data[1],
+                )
+            })
+    }
+}
+
+/// reads a module matrix back into segments. the version comes from the width, the format from
+/// whichever copy is least damaged, and each block gets reed solomon corrected
+pub fn decode(data: &[Vec<bool>]) -> Result<Decoded, Error> {
+    let version = layout::check_square(data)?;
+    // from version 7 the symbol says its version as well, a width that doesn't match means the
+    // grid was sampled wrong or isn't a symbol at all
+    if layout::read_version(data)?.0 != version {
+        return Err(Error::InvalidWidth(data.len()));
+    }
+    let (ec, mask, format_errors) = layout::read_format(data)?;
+    let codewords = layout::read_codewords(data, mask)?;
+    let (data, errors_corrected) = encoding::deinterleave_and_correct(&codewords, version, ec)?;
+    Ok(Decoded {
+        version,
+        ec,
+        mask,
+        segments: encoding::decode_segments(&data, version)?,
+        errors_corrected,
+        format_errors,
+    })
+}
+
+/// finds the symbol in rows of luminance values, like the ones `bitmap::read_bitmap` gives, and
+/// decodes it. clean renderings are tried first, then it falls back to the detector for photos
+pub fn decode_image(image: &[Vec<u8>]) -> Result<Decoded, Error> {
+    scan::sample_grid(image)
+        .and_then(|grid| decode(&grid.modules))
+        .or_else(|_| decode(&scan::detect(image)?.modules))
+}
+
+/// reed solomon corrects a block of data codewords followed by `ec_codewords` ec codewords in
+/// place, `erasures` are positions in the block known to be wrong. returns how many codewords
+/// were changed
+pub fn correct_block(
+    block: &mut [u8],
+    ec_codewords: usize,
+    erasures: &[usize],
+) -> Result<usize, Error> {
+    rsec::rs_decode(block, ec_codewords, erasures)
+}
+
+impl Qr {
+    /// reads the symbol back, see `decode`
+    pub fn decode(&self) -> Result<Decoded, Error> {
+        decode(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+    use crate::encoding::Fnc1;
+    use crate::layout::ModuleOrder;
+    use crate::netpbm;
+    use crate::render::RenderOptions;
+    use crate::style::{EyeStyle, ModuleStyle, Style};
+
+    #[test]
+    fn test_round_trip() {
+        let cases = [
+            "HELLO WORLD",
+            "hello world",
+            "0123456789012345678901234567890",
+            "ｱｲｳ茗荷 and some text 12345678901234",
+            "点茗",
+        ];
+        for text in cases {
+            for ec in ECLevel::ASCENDING {
+                let (qr, _) = QrBuilder::new(text).ec(ec).build().unwrap();
+                let decoded = qr.decode().unwrap();
+                assert_eq!(decoded.text(), text);
+                assert_eq!(decoded.segments, qr.segments());
+                assert_eq!(
+                    (decoded.version, decoded.ec, Some(decoded.mask)),
+                    (qr.version(), qr.ec(), qr.mask())
+                );
+                assert_eq!(decoded.errors_corrected, 0);
+            }
+        }
+
+        for (version, mask) in [(7, 0), (14, 3), (27, 6), (40, 7)] {
+            let data: Vec<u8> = (0..100u32).map(|n| (n * 37) as u8).collect();
+            let (qr, _) = QrBuilder::from_bytes(&data)
+                .version(version)
+                .mask(mask)
+                .eci(3)
+                .build()
+                .unwrap();
+            let decoded = qr.decode().unwrap();
+            assert_eq!(decoded.content(), data);
+            assert_eq!(decoded.segments, qr.segments());
+        }
+    }
+
+    #[test]
+    fn test_fnc1() {
+        let text = "0104912345123459\x1D10ABC%123";
+        let (qr, _) = QrBuilder::new(text).fnc1(Fnc1::First).build().unwrap();
+        assert_eq!(qr.decode().unwrap().text(), text);
+    }
+
+    #[test]
+    fn test_structured_append() {
+        let codes = QrBuilder::from_bytes(&[0x55; 500])
+            .max_version(10)
+            .build_split()
+            .unwrap();
+        let mut joined = vec![];
+        for (i, (qr, _)) in codes.iter().enumerate() {
+            let decoded = qr.decode().unwrap();
+            assert_eq!(decoded.structured_append(), Some((i, 2, 0)));
+            joined.extend(decoded.content());
+        }
+        assert_eq!(joined, vec![0x55; 500]);
+    }
+
+    #[test]
+    fn test_structured_append_fnc1() {
+        // splits never land between the two halves of an escaped "%", and parity is over the
+        // message before escaping
+        let text = format!("{}10\x1D", "A%".repeat(40));
+        let parity = text.bytes().fold(0, |acc, b| acc ^ b);
+        for version in 1..=3 {
+            let codes = QrBuilder::new(&text)
+                .fnc1(Fnc1::First)
+                .mode(Mode::Alphanumeric)
+                .max_version(version)
+                .build_split()
+                .unwrap();
+            assert!(codes.len() > 1);
+            let mut joined = vec![];
+            for (i, (qr, _)) in codes.iter().enumerate() {
+                let decoded = qr.decode().unwrap();
+                assert_eq!(decoded.structured_append(), Some((i, codes.len(), parity)));
+                joined.extend(decoded.content());
+            }
+            assert_eq!(joined, text.as_bytes(), "version {}", version);
+        }
+    }
+
+    #[test]
+    fn test_decode_image() {
+        let (qr, _) = QrBuilder::new("read back from an image").build().unwrap();
+        let pbm = netpbm::qr_to_pbm(&qr, &RenderOptions::default(), false).unwrap();
+        let decoded = decode_image(&netpbm::read_netpbm(&pbm).unwrap()).unwrap();
+        assert_eq!(decoded.text(), "read back from an image");
+    }
+
+    #[test]
+    fn test_decode_styled_image() {
+        let (qr, _) = QrBuilder::new("read back with round modules")
+            .version(12)
+            .build()
+            .unwrap();
+        for modules in [
+            ModuleStyle::Circle,
+            ModuleStyle::Rounded,
+            ModuleStyle::Connected,
+        ] {
+            for eye in [EyeStyle::Rounded, EyeStyle::Circle] {
+                let options = RenderOptions {
+                    scale: 4,
+                    style: Style {
+                        modules,
+                        eye_ring: eye,
+                        eye_pupil: eye,
+                    },
+                    ..Default::default()
+                };
+                let pbm = netpbm::qr_to_pbm(&qr, &options, false).unwrap();
+                let decoded = decode_image(&netpbm::read_netpbm(&pbm).unwrap()).unwrap();
+                assert_eq!(decoded.text(), "read back with round modules");
+            }
+        }
+    }
+
+    #[test]
+    fn test_damage() {
+        let (mut qr, _) = QrBuilder::new("damage tolerance testing")
+            .ec(ECLevel::High)
+            .version(3)
+            .build()
+            .unwrap();
+        // 3 bad codewords out of 11 correctable per block
+        let order: Vec<_> = ModuleOrder::new(3).unwrap().collect();
+        for pos in order.iter().step_by(8).take(3) {
+            qr.data[pos.0][pos.1] = !qr.data[pos.0][pos.1];
+        }
+        let decoded = qr.decode().unwrap();
+        assert_eq!(decoded.text(), "damage tolerance testing");
+        assert_eq!(decoded.errors_corrected, 3);
+
+        for pos in order.iter().take(200) {
+            qr.data[pos.0][pos.1] = !qr.data[pos.0][pos.1];
+        }
+        assert!(qr.decode().is_err());
+
+        assert_eq!(
+            decode(&vec![vec![false; 20]; 20]),
+            Err(Error::InvalidWidth(20))
+        );
+    }
+
+    #[test]
+    fn test_correct_block() {
+        let block = rsec::rs_encode(b"hello world", 10).unwrap();
+        let mut damaged = block.clone();
+        damaged[0] ^= 0xFF;
+        damaged[5] ^= 0x01;
+        damaged[12] = 0;
+        assert_eq!(correct_block(&mut damaged, 10, &[12]), Ok(3));
+        assert_eq!(damaged, block);
+
+        assert_eq!(correct_block(&mut [], 0, &[]), Ok(0));
+        assert_eq!(correct_block(&mut [], 4, &[]), Ok(0));
+        assert_eq!(
+            correct_block(&mut [1, 2], 1, &[2]),
+            Err(Error::InvalidErasure(2))
+        );
+        assert_eq!(
+            correct_block(&mut [0; 256], 10, &[]),
+            Err(Error::BlockTooLong(256))
+        );
+    }
+
+    #[test]
+    fn test_version_mismatch() {
+        let (mut qr, _) = QrBuilder::new("version check").version(7).build().unwrap();
+        layout::draw_version(&mut qr.data, 8);
+        assert_eq!(qr.decode(), Err(Error::InvalidWidth(45)));
+        layout::draw_version(&mut qr.data, 7);
+        assert_eq!(qr.decode().unwrap().text(), "version check");
+    }
+}
@@ -0,0 +1,266 @@
+// just enough of zlib to compress png image data. everything goes in one block with the fixed
+// huffman codes, which does fine on images where most rows repeat the one above
+
+const WINDOW: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// how many earlier positions with the same hash get checked for a match
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+const LENGTH_BASE: [usize; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [usize; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [usize; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [usize; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// deflate bits go in least significant bit first
+#[derive(Debug, Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: usize, bits: usize) {
+        for i in 0..bits {
+            if self.len.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.data.last_mut().unwrap() |= 1 << (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+
+    // huffman codes go in most significant bit first
+    fn write_code(&mut self, code: usize, bits: usize) {
+        for i in (0..bits).rev() {
+            self.write((code >> i) & 1, 1);
+        }
+    }
+
+    fn write_symbol(&mut self, symbol: usize) {
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_match(&mut self, len: usize, dist: usize) {
+        let i = LENGTH_BASE.iter().rposition(|&base| base <= len).unwrap();
+        self.write_symbol(257 + i);
+        self.write(len - LENGTH_BASE[i], LENGTH_EXTRA[i]);
+        let i = DIST_BASE.iter().rposition(|&base| base <= dist).unwrap();
+        self.write_code(i, 5);
+        self.write(dist - DIST_BASE[i], DIST_EXTRA[i]);
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    let n = (data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32;
+    (n.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// raw deflate data for `data`
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut res = BitWriter::default();
+    res.write(1, 1); // last block
+    res.write(1, 2); // fixed huffman codes
+
+    // the latest position for each hash, and the one before it with the same hash
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; data.len()];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let (len, dist) = longest_match(data, pos, &head, &prev);
+        if len >= MIN_MATCH {
+            res.write_match(len, dist);
+        } else {
+            res.write_symbol(data[pos] as usize);
+        }
+        for i in (pos..pos + len.max(1)).filter(|i| i + MIN_MATCH <= data.len()) {
+            let h = hash(&data[i..]);
+            prev[i] = head[h];
+            head[h] = i;
+        }
+        pos += len.max(1);
+    }
+    res.write_symbol(256);
+    res.data
+}
+
+// the length and distance of the longest earlier copy of the data at `pos`
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let limit = MAX_MATCH.min(data.len() - pos);
+    let mut best = (0, 0);
+    let mut candidate = head[hash(&data[pos..])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || pos - candidate > WINDOW {
+            break;
+        }
+        // copies can overlap the data they're copying
+        let len = (0..limit)
+            .take_while(|&i| data[candidate + i] == data[pos + i])
+            .count();
+        if len > best.0 {
+            best = (len, pos - candidate);
+            if len == limit {
+                break;
+            }
+        }
+        candidate = prev[candidate];
+    }
+    best
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// `data` deflated with a zlib header and checksum
+pub(crate) fn zlib(data: &[u8]) -> Vec<u8> {
+    // 32k window, default compression, and a check value making the header a multiple of 31
+    let mut res = vec![0x78, 0x9C];
+    res.extend(deflate(data));
+    res.extend(adler32(data).to_be_bytes());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // decoder for what `deflate` writes, single blocks with fixed codes
+    fn inflate(data: &[u8]) -> Vec<u8> {
+        let mut pos = 0;
+        let mut read = |bits: usize| {
+            let mut res = 0;
+            for i in 0..bits {
+                res |= ((data[pos / 8] >> (pos % 8)) as usize & 1) << i;
+                pos += 1;
+            }
+            res
+        };
+        assert_eq!(read(3), 0b011);
+        let mut res: Vec<u8> = vec![];
+        loop {
+            let mut code = 0;
+            let mut len = 0;
+            let symbol = loop {
+                code = code << 1 | read(1);
+                len += 1;
+                match (len, code) {
+                    (7, 0..=0x17) => break code + 256,
+                    (8, 0x30..=0xBF) => break code - 0x30,
+                    (8, 0xC0..=0xC7) => break code - 0xC0 + 280,
+                    (9, 0x190..) => break code - 0x190 + 144,
+                    _ => (),
+                }
+            };
+            match symbol {
+                0..=255 => res.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let i = symbol - 257;
+                    let len = LENGTH_BASE[i] + read(LENGTH_EXTRA[i]);
+                    let mut i = 0;
+                    for _ in 0..5 {
+                        i = i << 1 | read(1);
+                    }
+                    let dist = DIST_BASE[i] + read(DIST_EXTRA[i]);
+                    for _ in 0..len {
+                        res.push(res[res.len() - dist]);
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_deflate() {
+        let mut noise: u32 = 1;
+        let random: Vec<u8> = (0..5000)
+            .map(|_| {
+                noise ^= noise << 13;
+                noise ^= noise >> 17;
+                noise ^= noise << 5;
+                noise as u8
+            })
+            .collect();
+        let repeats: Vec<u8> = (0..100_000u32).map(|n| (n / 700 % 3) as u8).collect();
+        let cases: [&[u8]; 5] = [b"", b"a", b"abcabcabcabcabcabc", &random, &repeats];
+        for data in cases {
+            assert_eq!(inflate(&deflate(data)), data);
+        }
+        assert!(deflate(&repeats).len() < 2000);
+
+        // what zlib writes for the same data with fixed codes only (Z_FIXED at level 6). with
+        // nothing to match the streams have to be identical
+        let literals: [(&[u8], &[u8]); 3] = [
+            (b"", &[0x03, 0x00]),
+            (b"\x00\x8F\x90\xFF", &[0x63, 0xE8, 0x9F, 0xF0, 0x1F, 0x00]),
+            (
+                b"hello world",
+                &[
+                    0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0x28, 0xCF, 0x2F, 0xCA, 0x49, 0x01, 0x00,
+                ],
+            ),
+        ];
+        for (data, stream) in literals {
+            assert_eq!(deflate(data), stream);
+        }
+        // zlib picks different matches from ours here, but its streams still have to read back
+        let matches: [(&[u8], &[u8]); 2] = [
+            (
+                b"hello hello hello",
+                &[0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x90, 0x00],
+            ),
+            (
+                b"abcabcabcabcabcabc",
+                &[0x4B, 0x4C, 0x4A, 0x4E, 0x44, 0x45, 0x00],
+            ),
+        ];
+        for (data, stream) in matches {
+            assert_eq!(inflate(stream), data);
+        }
+    }
+
+    #[test]
+    fn test_zlib() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(
+            zlib(b"a"),
+            [0x78, 0x9C, 0x4B, 0x04, 0x00, 0x00, 0x62, 0x00, 0x62]
+        );
+        let res = zlib(b"hello hello hello");
+        assert_eq!(u16::from_be_bytes([res[0], res[1]]) % 31, 0);
+        assert_eq!(
+            &res[res.len() - 4..],
+            adler32(b"hello hello hello").to_be_bytes()
+        );
+    }
+}
@@ -1,20 +1,83 @@
 use clap::ValueEnum;
 
 use std::collections::VecDeque;
+use std::iter;
+use std::ops::RangeInclusive;
 
 use crate::{
-    bitstream::Bitstream,
+    bitstream::{BitReader, Bitstream},
+    error::Error,
     rsec,
-    tables::{ALPHANUMERIC_ORDER, BLOCK_GROUPS, DATA_CAPACITY, LENGTH_BITS},
+    tables::{ALPHANUMERIC_ORDER, BLOCK_GROUPS, DATA_CAPACITY, KANJI_TO_UNICODE, LENGTH_BITS},
 };
 
-#[allow(unused)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Mode {
     Numeric = 0b0001,
     Alphanumeric = 0b0010,
     Byte = 0b0100,
     Kanji = 0b1000,
+    Eci = 0b0111,
+    StructuredAppend = 0b0011,
+    Fnc1First = 0b0101,
+    Fnc1Second = 0b1001,
+}
+
+impl Mode {
+    /// eci, structured append and fnc1 segments have no length indicator, just a fixed header
+    fn has_length(self) -> bool {
+        !matches!(
+            self,
+            Mode::Eci | Mode::StructuredAppend | Mode::Fnc1First | Mode::Fnc1Second
+        )
+    }
+
+    fn from_bits(bits: u32) -> Option<Self> {
+        [
+            Mode::Numeric,
+            Mode::Alphanumeric,
+            Mode::Byte,
+            Mode::Kanji,
+            Mode::Eci,
+            Mode::StructuredAppend,
+            Mode::Fnc1First,
+            Mode::Fnc1Second,
+        ]
+        .into_iter()
+        .find(|mode| *mode as u32 == bits)
+    }
+}
+
+/// ECI assignment number for UTF-8
+pub const UTF8_ECI: u32 = 26;
+
+/// GS1 group separator, marks the end of a variable length field in FNC1 data
+pub const GS: char = '\x1D';
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Fnc1 {
+    /// GS1 application identifier data
+    First,
+    /// data in an industry format with this application indicator, either a two digit number or
+    /// an ascii letter plus 100
+    Second(u8),
+}
+
+impl Fnc1 {
+    /// the header segment, fails if the application indicator isn't a valid one
+    pub fn segment(self) -> Result<Segment, Error> {
+        Ok(match self {
+            Fnc1::First => Segment {
+                mode: Mode::Fnc1First,
+                data: vec![],
+            },
+            Fnc1::Second(app) if app < 100 || (app - 100).is_ascii_alphabetic() => Segment {
+                mode: Mode::Fnc1Second,
+                data: vec![app],
+            },
+            Fnc1::Second(app) => return Err(Error::InvalidApplicationIndicator(app)),
+        })
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, ValueEnum)]
@@ -25,12 +88,239 @@ pub enum ECLevel {
     High = 0b10,
 }
 
-// should add kanji mode and potentially support for mixing modes
+impl ECLevel {
+    /// from least to most error correction
+    pub const ASCENDING: [ECLevel; 4] = [
+        ECLevel::Low,
+        ECLevel::Medium,
+        ECLevel::Quartile,
+        ECLevel::High,
+    ];
+
+    /// the level for the 2 ec bits in the format information
+    pub fn from_format_bits(bits: usize) -> Self {
+        match bits & 0b11 {
+            0b01 => ECLevel::Low,
+            0b00 => ECLevel::Medium,
+            0b11 => ECLevel::Quartile,
+            _ => ECLevel::High,
+        }
+    }
+
+    fn strength(self) -> usize {
+        Self::ASCENDING.iter().position(|l| *l == self).unwrap()
+    }
+}
+
+// ordered by how much error correction there is, not the format bits
+impl Ord for ECLevel {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.strength().cmp(&other.strength())
+    }
+}
+
+impl PartialOrd for ECLevel {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A run of data encoded in a single mode
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Segment {
+    pub mode: Mode,
+    /// ascii characters for numeric and alphanumeric, raw bytes for byte, shift jis byte pairs
+    /// for kanji, the encoded assignment number for eci, the position and parity bytes for
+    /// structured append and the application indicator for fnc1 in the second position
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// makes a segment from text
+    pub fn new(data: &str, mode: Mode) -> Result<Self, Error> {
+        let unencodable = |valid: fn(char) -> bool| {
+            data.chars()
+                .find(|c| !valid(*c))
+                .map_or(Ok(()), |c| Err(Error::UnencodableCharacter(c)))
+        };
+        let data = match mode {
+            Mode::Numeric => {
+                unencodable(|c| c.is_ascii_digit())?;
+                data.as_bytes().to_vec()
+            }
+            Mode::Alphanumeric => {
+                unencodable(|c| ALPHANUMERIC_ORDER.contains(&c))?;
+                data.as_bytes().to_vec()
+            }
+            Mode::Byte => data.as_bytes().to_vec(),
+            Mode::Kanji => data
+                .chars()
+                .map(|c| {
+                    char_to_kanji(c)
+                        .map(|k| kanji_to_sjis(k).to_be_bytes())
+                        .ok_or(Error::UnencodableCharacter(c))
+                })
+                .collect::<Result<Vec<[u8; 2]>, Error>>()?
+                .concat(),
+            _ => return Err(Error::InvalidMode(mode)),
+        };
+        Ok(Self { mode, data })
+    }
+
+    /// makes a byte mode segment from raw bytes
+    pub fn bytes(data: &[u8]) -> Self {
+        Self {
+            mode: Mode::Byte,
+            data: data.to_vec(),
+        }
+    }
+
+    /// makes an eci segment, assignment numbers go up to 999999
+    pub fn eci(assignment: u32) -> Result<Self, Error> {
+        let data = match assignment {
+            0..128 => vec![assignment as u8],
+            128..16384 => (0x8000 | assignment as u16).to_be_bytes().to_vec(),
+            16384..1_000_000 => (0xC00000 | assignment).to_be_bytes()[1..].to_vec(),
+            _ => return Err(Error::InvalidEci(assignment)),
+        };
+        Ok(Self {
+            mode: Mode::Eci,
+            data,
+        })
+    }
+
+    /// makes a structured append header for symbol `index` (from 0) of `total` symbols, there can
+    /// be at most 16 symbols
+    pub fn structured_append(index: usize, total: usize, parity: u8) -> Result<Self, Error> {
+        if !(1..=16).contains(&total) || index >= total {
+            return Err(Error::TooManySymbols(total.max(index + 1)));
+        }
+        Ok(Self {
+            mode: Mode::StructuredAppend,
+            data: vec![((index << 4) | (total - 1)) as u8, parity],
+        })
+    }
+
+    /// splits the segment after `chars` characters
+    pub fn split_at(&self, chars: usize) -> (Self, Self) {
+        let bytes = if self.mode == Mode::Kanji {
+            chars * 2
+        } else {
+            chars
+        };
+        let (a, b) = self.data.split_at(bytes);
+        (
+            Self {
+                mode: self.mode,
+                data: a.to_vec(),
+            },
+            Self {
+                mode: self.mode,
+                data: b.to_vec(),
+            },
+        )
+    }
+
+    /// number of characters the length indicator counts
+    pub fn char_count(&self) -> usize {
+        match self.mode {
+            Mode::Kanji => self.data.len() / 2,
+            _ => self.data.len(),
+        }
+    }
+
+    /// length in bits including the mode and length indicators, fails if the segment is too long
+    /// for the length indicator of this version
+    pub fn bit_len(&self, version: usize) -> Result<usize, Error> {
+        if !self.mode.has_length() {
+            return Ok(4 + self.data.len() * 8);
+        }
+        let length_bits = get_length_bits(self.mode, version)?;
+        if self.char_count() >= 1 << length_bits {
+            return Err(Error::DataTooLong);
+        }
+        Ok(4 + length_bits + data_len(self.mode, self.char_count()))
+    }
+
+    fn encode(&self, res: &mut Bitstream, version: usize) -> Result<(), Error> {
+        // the fields are public, so check the data suits the mode and fits the length indicator
+        // before writing any of it
+        self.bit_len(version)?;
+        let invalid = match self.mode {
+            Mode::Numeric => self.data.iter().find(|b| !b.is_ascii_digit()),
+            Mode::Alphanumeric => self
+                .data
+                .iter()
+                .find(|&&b| !ALPHANUMERIC_ORDER.contains(&(b as char))),
+            _ => None,
+        };
+        if let Some(&b) = invalid {
+            return Err(Error::UnencodableCharacter(b as char));
+        }
+        let kanji = match self.mode {
+            Mode::Kanji if self.data.len() % 2 == 1 => return Err(Error::Malformed),
+            Mode::Kanji => self
+                .data
+                .chunks_exact(2)
+                .map(|pair| sjis_to_kanji(u16::from_be_bytes([pair[0], pair[1]])))
+                .collect::<Option<Vec<u16>>>()
+                .ok_or(Error::Malformed)?,
+            _ => vec![],
+        };
+
+        // mode indicator
+        res.push_u8(self.mode as u8, 4);
+
+        // eci and structured append have no length indicator
+        if !self.mode.has_length() {
+            res.push_bytes(&self.data);
+            return Ok(());
+        }
+
+        // length indicator
+        res.push_u16(
+            self.char_count() as u16,
+            get_length_bits(self.mode, version)?,
+        );
+
+        match self.mode {
+            Mode::Numeric => {
+                for chunk in self.data.chunks(3) {
+                    let num = chunk.iter().fold(0, |acc, d| acc * 10 + (*d - b'0') as u16);
+                    res.push_u16(num, [0, 4, 7, 10][chunk.len()]);
+                }
+            }
+            Mode::Alphanumeric => {
+                for chunk in self.data.chunks(2) {
+                    if chunk.len() == 1 {
+                        res.push_u16(char_to_alphanum(chunk[0] as char), 6);
+                    } else {
+                        let code = (45 * char_to_alphanum(chunk[0] as char))
+                            + char_to_alphanum(chunk[1] as char);
+                        res.push_u16(code, 11);
+                    }
+                }
+            }
+            Mode::Kanji => {
+                for value in kanji {
+                    res.push_u16(value, 13);
+                }
+            }
+            // byte mode, the header only modes were written above
+            _ => res.push_bytes(&self.data),
+        }
+        Ok(())
+    }
+}
+
+/// picks a single mode for the whole string, see `segment` for mixing modes
 pub fn detect_mode(data: &str) -> Mode {
     if is_numeric(data) {
         Mode::Numeric
     } else if is_alphanumeric(data) {
         Mode::Alphanumeric
+    } else if is_kanji(data) {
+        Mode::Kanji
     } else {
         Mode::Byte
     }
@@ -44,14 +334,25 @@ fn is_alphanumeric(data: &str) -> bool {
     data.chars().all(|c| ALPHANUMERIC_ORDER.contains(&c))
 }
 
-pub fn get_length_bits(mode: Mode, version: usize) -> Option<usize> {
+fn is_kanji(data: &str) -> bool {
+    !data.is_empty() && data.chars().all(|c| char_to_kanji(c).is_some())
+}
+
+pub fn get_length_bits(mode: Mode, version: usize) -> Result<usize, Error> {
     let index = match version {
         1..=9 => 0,
         10..=26 => 1,
         27..=40 => 2,
-        _ => return None,
+        _ => return Err(Error::InvalidVersion(version)),
+    };
+    let row = match mode {
+        Mode::Numeric => 0,
+        Mode::Alphanumeric => 1,
+        Mode::Byte => 2,
+        Mode::Kanji => 3,
+        _ => return Ok(0),
     };
-    Some(LENGTH_BITS[(mode as u32).ilog2() as usize][index])
+    Ok(LENGTH_BITS[row][index])
 }
 
 pub fn data_len(mode: Mode, len: usize) -> usize {
@@ -60,75 +361,378 @@ pub fn data_len(mode: Mode, len: usize) -> usize {
             (len / 3) * 10 + ((len % 3 == 1) as usize) * 4 + ((len % 3 == 2) as usize) * 7
         }
         Mode::Alphanumeric => ((len / 2) * 11) + ((len & 1) * 6),
-        Mode::Byte => len * 8,
-        Mode::Kanji => unimplemented!(),
+        Mode::Kanji => len * 13,
+        _ => len * 8,
     }
 }
 
+/// total length in bits of the segments for this version, not including the terminator
+pub fn bit_len(segments: &[Segment], version: usize) -> Result<usize, Error> {
+    segments.iter().map(|s| s.bit_len(version)).sum()
+}
+
 // find smallest version that fits data
-pub fn detect_version(mode: Mode, len: usize, ec: ECLevel) -> Option<usize> {
-    for (v, row) in DATA_CAPACITY.iter().enumerate() {
-        let capacity = row[ec as usize] * 8;
-        // 8 extra bits for mode selector + terminator
-        let size = (8 + get_length_bits(mode, v + 1)? + len).next_multiple_of(8);
-        if size <= capacity {
-            return Some(v + 1);
+pub fn detect_version(segments: &[Segment], ec: ECLevel) -> Result<usize, Error> {
+    (1..=40)
+        .find(|v| fits(segments, *v, ec))
+        .ok_or(Error::DataTooLong)
+}
+
+/// number of data codewords, including padding, for this version and ec level
+pub fn data_codewords(version: usize, ec: ECLevel) -> Result<usize, Error> {
+    DATA_CAPACITY
+        .get(version.wrapping_sub(1))
+        .map(|row| row[ec as usize])
+        .ok_or(Error::InvalidVersion(version))
+}
+
+/// do the segments fit in this version at this ec level? nothing fits in an invalid version
+pub fn fits(segments: &[Segment], version: usize, ec: ECLevel) -> bool {
+    match (bit_len(segments, version), data_codewords(version, ec)) {
+        (Ok(len), Ok(codewords)) => len <= codewords * 8,
+        _ => false,
+    }
+}
+
+/// splits data into segments for the smallest version at or above min_version that fits it,
+/// optionally declaring UTF-8 with an eci segment if there's any non ascii byte mode data and
+/// marking it as fnc1 data
+pub fn detect_segments(
+    data: &str,
+    ec: ECLevel,
+    min_version: usize,
+    utf8_eci: bool,
+    fnc1: Option<Fnc1>,
+) -> Result<(usize, Vec<Segment>), Error> {
+    detect_version_with(min_version..=40, ec, |version| {
+        let mut segments = match fnc1 {
+            Some(fnc1) => {
+                let mut res = segment_fnc1(data, version);
+                res.insert(0, fnc1.segment()?);
+                res
+            }
+            None => segment(data, version),
+        };
+        if utf8_eci {
+            insert_utf8_eci(&mut segments);
+        }
+        Ok(segments)
+    })
+}
+
+/// finds the smallest version in `versions` that fits the segments from `make_segments`, which
+/// only gets called with the first version of each range where the length indicators are the same
+/// size since that's the only time the best segmentation can change
+pub fn detect_version_with(
+    versions: RangeInclusive<usize>,
+    ec: ECLevel,
+    mut make_segments: impl FnMut(usize) -> Result<Vec<Segment>, Error>,
+) -> Result<(usize, Vec<Segment>), Error> {
+    for v in [*versions.start(), *versions.end()] {
+        if !(1..=40).contains(&v) {
+            return Err(Error::InvalidVersion(v));
+        }
+    }
+    if versions.is_empty() {
+        return Err(Error::InvalidVersionRange(
+            *versions.start(),
+            *versions.end(),
+        ));
+    }
+    for class in [1..=9, 10..=26, 27..=40] {
+        let start = *class.start().max(versions.start());
+        let end = *class.end().min(versions.end());
+        if start > end {
+            continue;
+        }
+        let segments = make_segments(start)?;
+        if let Some(version) = (start..=end).find(|v| fits(&segments, *v, ec)) {
+            return Ok((version, segments));
         }
     }
-    None
+    Err(Error::DataTooLong)
 }
 
-fn char_to_alphanum(data: char) -> u16 {
-    ALPHANUMERIC_ORDER.iter().position(|c| *c == data).unwrap() as u16
+/// declares UTF-8 at the start if there's any non ascii byte mode data
+pub fn insert_utf8_eci(segments: &mut Vec<Segment>) {
+    if segments
+        .iter()
+        .any(|s| s.mode == Mode::Byte && !s.data.is_ascii())
+    {
+        segments.insert(0, Segment::eci(UTF8_ECI).unwrap());
+    }
 }
 
-pub fn encode(data: &str, mode: Mode, version: usize, ec: ECLevel) -> Option<Vec<u8>> {
-    let num_codewords = DATA_CAPACITY[version - 1][ec as usize];
+/// splits segments across up to 16 symbols of this version, each starting with a structured
+/// append header
+pub fn structured_append(
+    segments: &[Segment],
+    version: usize,
+    ec: ECLevel,
+) -> Result<Vec<Vec<Segment>>, Error> {
+    // eci and fnc1 at the start apply to the whole message so they get repeated in every symbol
+    let (prefix, segments) =
+        segments.split_at(segments.iter().take_while(|s| !s.mode.has_length()).count());
+    // parity is over the message as it was given, so fnc1 escapes are undone first
+    let fnc1 = prefix
+        .iter()
+        .any(|s| matches!(s.mode, Mode::Fnc1First | Mode::Fnc1Second));
+    let parity = segments
+        .iter()
+        .flat_map(|s| match s.mode {
+            Mode::Alphanumeric if fnc1 => unescape_fnc1(&s.data),
+            _ => s.data.clone(),
+        })
+        .fold(0, |acc, b| acc ^ b);
+    let too_long = Error::DataTooLongForVersion(version, ec);
+    let header_len = 20 + bit_len(prefix, version)?;
+    let capacity = (data_codewords(version, ec)? * 8)
+        .checked_sub(header_len)
+        .ok_or(too_long.clone())?;
 
-    let mut res = Bitstream::new();
+    let mut parts: Vec<Vec<Segment>> = vec![vec![]];
+    let mut used = 0;
+    for segment in segments {
+        let mut segment = segment.clone();
+        loop {
+            // the segment can be too long for the length indicator on its own, so this works out
+            // how many characters fit without asking for the length of the whole thing
+            let fitting = match segment.mode.has_length() {
+                true => {
+                    let length_bits = get_length_bits(segment.mode, version)?;
+                    let head = 4 + length_bits;
+                    let most = segment.char_count().min((1 << length_bits) - 1);
+                    // each symbol's fnc1 data is unescaped on its own, so a split between the
+                    // two halves of "%%" would read back as two `GS`
+                    let escaped = fnc1 && segment.mode == Mode::Alphanumeric;
+                    (0..=most)
+                        .rev()
+                        .filter(|n| !(escaped && inside_fnc1_escape(&segment.data, *n)))
+                        .find(|n| used + head + data_len(segment.mode, *n) <= capacity)
+                        .map(|n| (n, head + data_len(segment.mode, n)))
+                }
+                // headers can't be split, they fit whole or not at all
+                false => Some((segment.char_count(), segment.bit_len(version)?))
+                    .filter(|(_, len)| used + len <= capacity),
+            };
+            match fitting {
+                Some((chars, len)) if chars == segment.char_count() => {
+                    parts.last_mut().unwrap().push(segment);
+                    used += len;
+                    break;
+                }
+                // fit as many characters as possible in this symbol and carry on in the next one
+                Some((chars, _)) if chars > 0 => {
+                    let (a, b) = segment.split_at(chars);
+                    parts.last_mut().unwrap().push(a);
+                    segment = b;
+                }
+                // nothing fits even in an empty symbol, so more symbols won't help
+                _ if used == 0 => return Err(too_long),
+                _ => (),
+            }
+            parts.push(vec![]);
+            used = 0;
+        }
+    }
 
-    // mode indicator
-    res.push_u8(mode as u8, 4);
+    let total = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(i, part)| {
+            let mut res = vec![Segment::structured_append(i, total, parity)?];
+            res.extend_from_slice(prefix);
+            res.extend(part);
+            Ok(res)
+        })
+        .collect()
+}
 
-    // length indicator
-    res.push_u16(data.len() as u16, get_length_bits(mode, version)?);
+// is `at` between the two characters of a "%%" escape in fnc1 alphanumeric data
+fn inside_fnc1_escape(data: &[u8], at: usize) -> bool {
+    let mut i = 0;
+    while i < at {
+        i += match &data[i..] {
+            [b'%', b'%', ..] => 2,
+            _ => 1,
+        };
+    }
+    i > at
+}
 
-    match mode {
-        Mode::Numeric => {
-            let mut chars = data.chars().peekable();
-            while chars.peek().is_some() {
-                let chunk: String = chars.by_ref().take(3).collect();
-                let len = if chunk.len() == 1 {
-                    4
-                } else if chunk.len() == 2 {
-                    7
-                } else {
-                    10
-                };
-                res.push_u16(chunk.parse().unwrap(), len);
+/// alphanumeric data from fnc1 symbols with "%%" back to "%" and a lone "%" back to `GS`
+pub(crate) fn unescape_fnc1(data: &[u8]) -> Vec<u8> {
+    let mut res = vec![];
+    let mut data = data.iter().peekable();
+    while let Some(&c) = data.next() {
+        if c != b'%' {
+            res.push(c);
+        } else if data.next_if_eq(&&b'%').is_some() {
+            res.push(b'%');
+        } else {
+            res.push(GS as u8);
+        }
+    }
+    res
+}
+
+const SEGMENT_MODES: [Mode; 4] = [Mode::Numeric, Mode::Alphanumeric, Mode::Byte, Mode::Kanji];
+
+/// split text into the segments with the shortest total bit length for this version
+pub fn segment(data: &str, version: usize) -> Vec<Segment> {
+    segment_with(data, version, false)
+}
+
+/// `segment` for fnc1 data, where `GS` is written as `%` in alphanumeric mode and `%` is escaped
+/// as `%%`
+pub fn segment_fnc1(data: &str, version: usize) -> Vec<Segment> {
+    segment_with(data, version, true)
+}
+
+fn segment_with(data: &str, version: usize, fnc1: bool) -> Vec<Segment> {
+    let chars: Vec<char> = data.chars().collect();
+    if chars.is_empty() {
+        return vec![];
+    }
+
+    // costs are in sixths of a bit so numeric and alphanumeric characters are whole numbers
+    let head_costs = SEGMENT_MODES.map(|m| (4 + get_length_bits(m, version).unwrap_or(0)) * 6);
+    let char_cost = |c: char, mode: Mode| match mode {
+        Mode::Numeric if c.is_ascii_digit() => Some(20),
+        Mode::Alphanumeric if fnc1 && c == '%' => Some(66),
+        Mode::Alphanumeric if fnc1 && c == GS => Some(33),
+        Mode::Alphanumeric if ALPHANUMERIC_ORDER.contains(&c) => Some(33),
+        Mode::Byte => Some(c.len_utf8() * 8 * 6),
+        Mode::Kanji if char_to_kanji(c).is_some() => Some(78),
+        _ => None,
+    };
+
+    // for each character and the mode we end in after it, which mode that character was in
+    let mut char_modes: Vec<[Option<usize>; 4]> = vec![];
+    let mut prev_costs = head_costs;
+    for c in chars.iter() {
+        let mut curr_costs = [usize::MAX; 4];
+        let mut curr_modes = [None; 4];
+        for (i, mode) in SEGMENT_MODES.iter().enumerate() {
+            if let Some(cost) = char_cost(*c, *mode) {
+                curr_costs[i] = prev_costs[i] + cost;
+                curr_modes[i] = Some(i);
             }
         }
-        Mode::Alphanumeric => {
-            let mut chars = data.chars().peekable();
-            while chars.peek().is_some() {
-                let chunk: Vec<char> = chars.by_ref().take(2).collect();
-                if chunk.len() == 1 {
-                    res.push_u16(char_to_alphanum(chunk[0]), 6);
-                } else {
-                    let code = (45 * char_to_alphanum(chunk[0])) + char_to_alphanum(chunk[1]);
-                    res.push_u16(code, 11);
+        // switching modes after this character
+        for to in 0..SEGMENT_MODES.len() {
+            for from in 0..SEGMENT_MODES.len() {
+                if curr_modes[from].is_none() {
+                    continue;
+                }
+                let cost = curr_costs[from].next_multiple_of(6) + head_costs[to];
+                if cost < curr_costs[to] {
+                    curr_costs[to] = cost;
+                    curr_modes[to] = Some(from);
                 }
             }
         }
-        Mode::Byte => {
-            for b in data.as_bytes() {
-                res.push_u8(*b, 8);
+        char_modes.push(curr_modes);
+        prev_costs = curr_costs;
+    }
+
+    // walk back through the cheapest path
+    let mut curr = (0..SEGMENT_MODES.len())
+        .min_by_key(|i| prev_costs[*i])
+        .unwrap();
+    let mut modes = vec![Mode::Byte; chars.len()];
+    for (i, choices) in char_modes.iter().enumerate().rev() {
+        curr = choices[curr].unwrap();
+        modes[i] = SEGMENT_MODES[curr];
+    }
+
+    // join runs of the same mode into segments
+    let mut res: Vec<Segment> = vec![];
+    let mut start = 0;
+    for i in 1..=chars.len() {
+        if i == chars.len() || modes[i] != modes[start] {
+            let mut text: String = chars[start..i].iter().collect();
+            if fnc1 && modes[start] == Mode::Alphanumeric {
+                text = text.replace('%', "%%").replace(GS, "%");
             }
+            res.push(Segment::new(&text, modes[start]).unwrap());
+            start = i;
         }
-        Mode::Kanji => unimplemented!(),
     }
+    res
+}
+
+fn char_to_alphanum(data: char) -> u16 {
+    ALPHANUMERIC_ORDER.iter().position(|c| *c == data).unwrap() as u16
+}
 
-    res.push_u8(0, 4); // insert terminator
+// every kanji mode character is in the basic multilingual plane, so this is indexed by code point
+// and holds the kanji mode value plus one, 0 for characters kanji mode doesn't have. it's filled
+// backwards so a character listed twice gets its first value
+const fn make_unicode_to_kanji() -> [u16; 0x10000] {
+    let mut res = [0; 0x10000];
+    let mut kanji = KANJI_TO_UNICODE.len();
+    while kanji > 0 {
+        kanji -= 1;
+        let c = KANJI_TO_UNICODE[kanji] as usize;
+        if c != 0 {
+            res[c] = kanji as u16 + 1;
+        }
+    }
+    res
+}
+
+static UNICODE_TO_KANJI: [u16; 0x10000] = make_unicode_to_kanji();
+
+/// 13 bit kanji mode value of a shift jis double byte character
+fn char_to_kanji(data: char) -> Option<u16> {
+    let kanji = *UNICODE_TO_KANJI.get(data as usize)?;
+    kanji.checked_sub(1)
+}
+
+/// the character for a 13 bit kanji mode value, None if it isn't mapped to one
+pub(crate) fn kanji_to_char(kanji: u16) -> Option<char> {
+    match KANJI_TO_UNICODE.get(kanji as usize) {
+        None | Some(0) => None,
+        Some(c) => char::from_u32(*c as u32),
+    }
+}
+
+fn kanji_to_sjis(kanji: u16) -> u16 {
+    let sjis = ((kanji / 0xC0) << 8) | (kanji % 0xC0);
+    if sjis + 0x8140 <= 0x9FFC {
+        sjis + 0x8140
+    } else {
+        sjis + 0xC140
+    }
+}
+
+/// the 13 bit kanji mode value of a shift jis double byte character, None if it's outside the
+/// ranges kanji mode covers
+pub(crate) fn sjis_to_kanji(sjis: u16) -> Option<u16> {
+    let offset = match sjis {
+        0x8140..=0x9FFC => 0x8140,
+        0xE040..=0xEBBF => 0xC140,
+        _ => return None,
+    };
+    let [high, low] = (sjis - offset).to_be_bytes();
+    (low < 0xC0).then_some(high as u16 * 0xC0 + low as u16)
+}
+
+pub fn encode(segments: &[Segment], version: usize, ec: ECLevel) -> Result<Vec<u8>, Error> {
+    let num_codewords = data_codewords(version, ec)?;
+
+    let mut res = Bitstream::new();
+    for segment in segments {
+        segment.encode(&mut res, version)?;
+    }
+    if res.bit_len() > num_codewords * 8 {
+        return Err(Error::DataTooLong);
+    }
+
+    // insert terminator, it can be cut short if there isn't enough space
+    res.push_u8(0, (num_codewords * 8 - res.bit_len()).min(4));
     res.push_u8(0, res.free_bits()); // fill remaining bits in last byte
 
     // insert padding
@@ -140,10 +744,10 @@ pub fn encode(data: &str, mode: Mode, version: usize, ec: ECLevel) -> Option<Vec
     res.push_bytes(&padding);
 
     let res_bytes = res.as_bytes();
-    Some(interleave_and_ec(&res_bytes, version, ec))
+    interleave_and_ec(&res_bytes, version, ec)
 }
 
-fn interleave_and_ec(bytes: &[u8], version: usize, ec: ECLevel) -> Vec<u8> {
+fn interleave_and_ec(bytes: &[u8], version: usize, ec: ECLevel) -> Result<Vec<u8>, Error> {
     let mut groups: Vec<VecDeque<u8>> = vec![];
     let mut ec_groups: Vec<VecDeque<u8>> = vec![];
     let mut res: Vec<u8> = vec![];
@@ -153,7 +757,7 @@ fn interleave_and_ec(bytes: &[u8], version: usize, ec: ECLevel) -> Vec<u8> {
     let ((num_ec_blocks, num_blocks, block_size), _) = BLOCK_GROUPS[version - 1][ec as usize];
     for _ in 0..num_blocks {
         let group: Vec<u8> = (&mut bytes_iter).take(block_size).collect();
-        let ec_group = rsec::rs_encode(&group, num_ec_blocks)[group.len()..].to_vec();
+        let ec_group = rsec::rs_encode(&group, num_ec_blocks)?[group.len()..].to_vec();
         groups.push(group.into());
         ec_groups.push(ec_group.into());
     }
@@ -164,7 +768,7 @@ fn interleave_and_ec(bytes: &[u8], version: usize, ec: ECLevel) -> Vec<u8> {
     {
         for _ in 0..num_blocks {
             let group: Vec<u8> = (&mut bytes_iter).take(block_size).collect();
-            let ec_group = rsec::rs_encode(&group, num_ec_blocks)[group.len()..].to_vec();
+            let ec_group = rsec::rs_encode(&group, num_ec_blocks)?[group.len()..].to_vec();
             groups.push(group.into());
             ec_groups.push(ec_group.into());
         }
@@ -193,7 +797,128 @@ fn interleave_and_ec(bytes: &[u8], version: usize, ec: ECLevel) -> Vec<u8> {
         }
     }
 
-    res
+    Ok(res)
+}
+
+/// parses data codewords back into segments, stopping at the terminator or the end of the data
+pub(crate) fn decode_segments(data: &[u8], version: usize) -> Result<Vec<Segment>, Error> {
+    let mut reader = BitReader::new(data);
+    let mut res = vec![];
+    while reader.remaining() >= 4 {
+        let mode = match reader.read(4) {
+            Some(0) => break,
+            bits => bits.and_then(Mode::from_bits).ok_or(Error::Malformed)?,
+        };
+        let mut read = |len| reader.read(len).ok_or(Error::Malformed);
+
+        let data = match mode {
+            Mode::Eci => {
+                let first = read(8)? as u8;
+                let extra = match first.leading_ones() {
+                    0 => 0,
+                    1 => 1,
+                    2 => 2,
+                    _ => return Err(Error::Malformed),
+                };
+                let mut data = vec![first];
+                for _ in 0..extra {
+                    data.push(read(8)? as u8);
+                }
+                data
+            }
+            Mode::StructuredAppend => vec![read(8)? as u8, read(8)? as u8],
+            Mode::Fnc1First => vec![],
+            Mode::Fnc1Second => vec![read(8)? as u8],
+            _ => {
+                let count = read(get_length_bits(mode, version)?)? as usize;
+                let mut data = vec![];
+                match mode {
+                    Mode::Numeric => {
+                        for chunk in (0..count).collect::<Vec<_>>().chunks(3) {
+                            let digits = chunk.len();
+                            let num = read([0, 4, 7, 10][digits])?;
+                            if num >= 10u32.pow(digits as u32) {
+                                return Err(Error::Malformed);
+                            }
+                            data.extend(format!("{:0digits$}", num).bytes());
+                        }
+                    }
+                    Mode::Alphanumeric => {
+                        for _ in 0..count / 2 {
+                            let code = read(11)? as usize;
+                            if code >= 45 * 45 {
+                                return Err(Error::Malformed);
+                            }
+                            data.push(ALPHANUMERIC_ORDER[code / 45] as u8);
+                            data.push(ALPHANUMERIC_ORDER[code % 45] as u8);
+                        }
+                        if count % 2 == 1 {
+                            let code = read(6)? as usize;
+                            data.push(*ALPHANUMERIC_ORDER.get(code).ok_or(Error::Malformed)? as u8);
+                        }
+                    }
+                    Mode::Kanji => {
+                        for _ in 0..count {
+                            data.extend(kanji_to_sjis(read(13)? as u16).to_be_bytes());
+                        }
+                    }
+                    // byte mode, the header only modes were handled above
+                    _ => {
+                        for _ in 0..count {
+                            data.push(read(8)? as u8);
+                        }
+                    }
+                }
+                data
+            }
+        };
+        res.push(Segment { mode, data });
+    }
+    Ok(res)
+}
+
+/// undoes `interleave_and_ec`, correcting each block. returns the data codewords and how many
+/// codewords were corrected
+pub(crate) fn deinterleave_and_correct(
+    codewords: &[u8],
+    version: usize,
+    ec: ECLevel,
+) -> Result<(Vec<u8>, usize), Error> {
+    let (group1, group2) = BLOCK_GROUPS[version - 1][ec as usize];
+    let num_ec_blocks = group1.0;
+    let mut sizes = vec![group1.2; group1.1];
+    if let Some((_, num_blocks, block_size)) = group2 {
+        sizes.extend(iter::repeat_n(block_size, num_blocks));
+    }
+    if codewords.len() != sizes.iter().sum::<usize>() + sizes.len() * num_ec_blocks {
+        return Err(Error::Malformed);
+    }
+
+    let mut blocks: Vec<Vec<u8>> = sizes
+        .iter()
+        .map(|size| Vec::with_capacity(size + num_ec_blocks))
+        .collect();
+    let mut codewords = codewords.iter().cloned();
+    for i in 0..*sizes.iter().max().unwrap() {
+        for (block, size) in blocks.iter_mut().zip(&sizes) {
+            if i < *size {
+                block.push(codewords.next().unwrap());
+            }
+        }
+    }
+    for _ in 0..num_ec_blocks {
+        for block in blocks.iter_mut() {
+            block.push(codewords.next().unwrap());
+        }
+    }
+
+    let mut res = vec![];
+    let mut corrected = 0;
+    for (mut block, size) in blocks.into_iter().zip(sizes) {
+        corrected += rsec::rs_decode(&mut block, num_ec_blocks, &[])?;
+        res.extend_from_slice(&block[..size]);
+    }
+    Ok((res, corrected))
 }
 
 #[cfg(test)]
@@ -206,15 +931,107 @@ mod tests {
         assert_eq!(detect_mode("123456ABC"), Mode::Alphanumeric);
         assert_eq!(detect_mode("123456ABCabc'!%&"), Mode::Byte);
         assert_eq!(detect_mode("123456ABCDEFabcdef'!%&¥"), Mode::Byte);
-        assert_eq!(detect_mode("一二三四五六七八九十"), Mode::Byte);
+        assert_eq!(detect_mode("一二三四五六七八九十"), Mode::Kanji);
+        assert_eq!(detect_mode("一二三abc"), Mode::Byte);
+    }
+
+    #[test]
+    fn test_segment_new() {
+        assert_eq!(
+            Segment::new("12a4", Mode::Numeric),
+            Err(Error::UnencodableCharacter('a'))
+        );
+        assert_eq!(
+            Segment::new("AbC", Mode::Alphanumeric),
+            Err(Error::UnencodableCharacter('b'))
+        );
+        assert_eq!(
+            Segment::new("点x", Mode::Kanji),
+            Err(Error::UnencodableCharacter('x'))
+        );
+        assert_eq!(
+            Segment::new("", Mode::Eci),
+            Err(Error::InvalidMode(Mode::Eci))
+        );
+    }
+
+    #[test]
+    fn test_invalid_segments() {
+        // segments built by hand instead of with `Segment::new`
+        let encode_one = |mode, data: &[u8]| {
+            let segment = Segment {
+                mode,
+                data: data.to_vec(),
+            };
+            encode(&[segment], 1, ECLevel::Low)
+        };
+        assert_eq!(
+            encode_one(Mode::Numeric, b"12/"),
+            Err(Error::UnencodableCharacter('/'))
+        );
+        assert_eq!(
+            encode_one(Mode::Alphanumeric, b"ab"),
+            Err(Error::UnencodableCharacter('a'))
+        );
+        assert_eq!(encode_one(Mode::Kanji, &[0x93]), Err(Error::Malformed));
+        assert_eq!(
+            encode_one(Mode::Kanji, &[0x00, 0x01]),
+            Err(Error::Malformed)
+        );
+        assert_eq!(
+            encode_one(Mode::Kanji, &[0xA0, 0x40]),
+            Err(Error::Malformed)
+        );
+        assert_eq!(
+            encode_one(Mode::Kanji, &[0x82, 0x30]),
+            Err(Error::Malformed)
+        );
+        assert_eq!(
+            encode_one(Mode::Numeric, &[b'1'; 1024]),
+            Err(Error::DataTooLong)
+        );
+    }
+
+    #[test]
+    fn test_kanji_lookup() {
+        // the same as searching the table for the first match
+        for &c in KANJI_TO_UNICODE.iter().filter(|&&c| c != 0) {
+            let expected = KANJI_TO_UNICODE.iter().position(|&k| k == c);
+            let c = char::from_u32(c as u32).unwrap();
+            assert_eq!(char_to_kanji(c).map(|k| k as usize), expected, "{:?}", c);
+        }
+        for c in ['\0', 'a', '\u{FFFF}', '\u{10000}', '\u{1F600}'] {
+            assert_eq!(char_to_kanji(c), None);
+        }
+    }
+
+    #[test]
+    fn test_sjis_to_kanji() {
+        for kanji in (0..8192).filter(|&k| KANJI_TO_UNICODE[k as usize] != 0) {
+            assert_eq!(sjis_to_kanji(kanji_to_sjis(kanji)), Some(kanji));
+        }
+        assert_eq!(sjis_to_kanji(0x935F), Some(0xD9F));
+        assert_eq!(sjis_to_kanji(0x8140 - 1), None);
+        assert_eq!(sjis_to_kanji(0xEBC0), None);
+    }
+
+    #[test]
+    fn test_ec_order() {
+        assert!(ECLevel::Low < ECLevel::Medium);
+        assert!(ECLevel::Medium < ECLevel::Quartile);
+        assert!(ECLevel::Quartile < ECLevel::High);
     }
 
     #[test]
     fn test_get_length_bits() {
-        assert_eq!(get_length_bits(Mode::Numeric, 1), Some(10));
-        assert_eq!(get_length_bits(Mode::Alphanumeric, 15), Some(11));
-        assert_eq!(get_length_bits(Mode::Byte, 29), Some(16));
-        assert_eq!(get_length_bits(Mode::Kanji, 14), Some(10));
+        assert_eq!(get_length_bits(Mode::Numeric, 1), Ok(10));
+        assert_eq!(get_length_bits(Mode::Alphanumeric, 15), Ok(11));
+        assert_eq!(get_length_bits(Mode::Byte, 29), Ok(16));
+        assert_eq!(get_length_bits(Mode::Kanji, 14), Ok(10));
+        assert_eq!(
+            get_length_bits(Mode::Kanji, 41),
+            Err(Error::InvalidVersion(41))
+        );
     }
 
     #[test]
@@ -225,6 +1042,195 @@ mod tests {
         assert_eq!(data_len(Mode::Numeric, 8), 27);
         assert_eq!(data_len(Mode::Alphanumeric, 4), 22);
         assert_eq!(data_len(Mode::Alphanumeric, 5), 28);
+        assert_eq!(data_len(Mode::Kanji, 2), 26);
+    }
+
+    #[test]
+    fn test_capacity() {
+        assert_eq!(data_codewords(1, ECLevel::Low), Ok(19));
+        assert_eq!(data_codewords(40, ECLevel::High), Ok(1276));
+        for version in [0, 41] {
+            assert_eq!(
+                data_codewords(version, ECLevel::Low),
+                Err(Error::InvalidVersion(version))
+            );
+            assert!(!fits(&[], version, ECLevel::Low));
+            assert_eq!(
+                structured_append(&[Segment::bytes(b"a")], version, ECLevel::Low),
+                Err(Error::InvalidVersion(version))
+            );
+        }
+        assert!(fits(&[Segment::bytes(&[0; 17])], 1, ECLevel::Low));
+        assert!(!fits(&[Segment::bytes(&[0; 18])], 1, ECLevel::Low));
+    }
+
+    #[test]
+    fn test_char_to_kanji() {
+        assert_eq!(char_to_kanji('点'), Some(0x0D9F));
+        assert_eq!(char_to_kanji('茗'), Some(0x1AAA));
+        assert_eq!(char_to_kanji('a'), None);
+        assert_eq!(char_to_kanji('\0'), None);
+    }
+
+    #[test]
+    fn test_encode_kanji() {
+        let segments = [Segment::new("点茗", Mode::Kanji).unwrap()];
+        assert_eq!(segments[0].data, vec![0x93, 0x5F, 0xE4, 0xAA]);
+        let res = encode(&segments, 1, ECLevel::Medium).unwrap();
+        // mode 1000, count 00000010, 0x0D9F and 0x1AAA as 13 bits each
+        assert_eq!(&res[..5], &[0x80, 0x26, 0xCF, 0xEA, 0xA8]);
+    }
+
+    #[test]
+    fn test_segment() {
+        let modes = |data: &str, version: usize| -> Vec<Mode> {
+            segment(data, version).iter().map(|s| s.mode).collect()
+        };
+        assert_eq!(modes("", 1), vec![]);
+        assert_eq!(modes("0123456789", 1), vec![Mode::Numeric]);
+        assert_eq!(modes("abc", 1), vec![Mode::Byte]);
+        assert_eq!(
+            modes("INVOICE 0000123456789 ref: abc", 1),
+            vec![Mode::Alphanumeric, Mode::Numeric, Mode::Byte]
+        );
+        assert_eq!(
+            modes("点茗点茗0123456789abc", 1),
+            vec![Mode::Kanji, Mode::Numeric, Mode::Byte]
+        );
+
+        let segments = segment("INVOICE 0000123456789 ref: abc", 1);
+        let text: Vec<u8> = segments.iter().flat_map(|s| s.data.clone()).collect();
+        assert_eq!(text, b"INVOICE 0000123456789 ref: abc");
+        let byte_only = [Segment::new("INVOICE 0000123456789 ref: abc", Mode::Byte).unwrap()];
+        assert!(bit_len(&segments, 1).unwrap() < bit_len(&byte_only, 1).unwrap());
+    }
+
+    #[test]
+    fn test_detect_segments() {
+        assert_eq!(
+            detect_segments("0123456789", ECLevel::Low, 1, false, None)
+                .unwrap()
+                .0,
+            1
+        );
+        assert_eq!(
+            detect_segments("0123456789", ECLevel::Low, 12, false, None)
+                .unwrap()
+                .0,
+            12
+        );
+        assert_eq!(
+            detect_segments(&"a".repeat(3000), ECLevel::Low, 1, false, None),
+            Err(Error::DataTooLong)
+        );
+    }
+
+    #[test]
+    fn test_eci() {
+        assert_eq!(Segment::eci(26).unwrap().data, vec![26]);
+        assert_eq!(Segment::eci(1000).unwrap().data, vec![0x83, 0xE8]);
+        assert_eq!(Segment::eci(100000).unwrap().data, vec![0xC1, 0x86, 0xA0]);
+        assert_eq!(Segment::eci(1_000_000), Err(Error::InvalidEci(1_000_000)));
+        assert_eq!(Segment::eci(26).unwrap().bit_len(1), Ok(12));
+
+        let (_, segments) = detect_segments("café", ECLevel::Low, 1, true, None).unwrap();
+        assert_eq!(segments[0], Segment::eci(UTF8_ECI).unwrap());
+        let (_, segments) = detect_segments("cafe", ECLevel::Low, 1, true, None).unwrap();
+        assert_eq!(segments[0].mode, Mode::Byte);
+
+        let res = encode(&[Segment::eci(UTF8_ECI).unwrap()], 1, ECLevel::Low).unwrap();
+        assert_eq!(&res[..2], &[0x71, 0xA0]);
+    }
+
+    #[test]
+    fn test_structured_append() {
+        let header = Segment::structured_append(2, 4, 0xAB).unwrap();
+        assert_eq!(header.data, vec![0x23, 0xAB]);
+        assert_eq!(header.bit_len(1), Ok(20));
+        assert_eq!(
+            Segment::structured_append(0, 17, 0),
+            Err(Error::TooManySymbols(17))
+        );
+
+        let data: Vec<u8> = (0..6000).map(|n| (n % 251) as u8).collect();
+        let mut segments = vec![Segment::eci(UTF8_ECI).unwrap(), Segment::bytes(&data)];
+        let parts = structured_append(&segments, 40, ECLevel::Low).unwrap();
+        assert_eq!(parts.len(), 3);
+        let parity = data.iter().fold(0, |acc, b| acc ^ b);
+        let mut joined = vec![];
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!(part[0], Segment::structured_append(i, 3, parity).unwrap());
+            assert_eq!(part[1].mode, Mode::Eci);
+            assert!(fits(part, 40, ECLevel::Low));
+            joined.extend(part[2..].iter().flat_map(|s| s.data.clone()));
+        }
+        assert_eq!(joined, data);
+
+        segments[1] = Segment::bytes(&[0; 50000]);
+        assert_eq!(
+            structured_append(&segments, 40, ECLevel::Low),
+            Err(Error::TooManySymbols(17))
+        );
+
+        // segments too long for the length indicator at smaller versions still get split
+        let data = vec![0x55; 300];
+        let parts = structured_append(&[Segment::bytes(&data)], 9, ECLevel::Low).unwrap();
+        assert_eq!(parts.len(), 2);
+        let digits = "1234567890".repeat(200);
+        let numeric = Segment::new(&digits, Mode::Numeric).unwrap();
+        let parts = structured_append(&[numeric], 5, ECLevel::Low).unwrap();
+        assert_eq!(parts.len(), 9);
+        let mut joined = vec![];
+        for part in &parts {
+            assert!(fits(part, 5, ECLevel::Low));
+            joined.extend(part[1..].iter().flat_map(|s| s.data.clone()));
+        }
+        assert_eq!(joined, digits.as_bytes());
+
+        // headers that leave no room for data, or don't fit at all
+        let too_long = Err(Error::DataTooLongForVersion(1, ECLevel::High));
+        let mut segments = vec![Segment::eci(UTF8_ECI).unwrap(); 3];
+        segments.push(Segment::bytes(b"hi"));
+        assert_eq!(structured_append(&segments, 1, ECLevel::High), too_long);
+        segments.splice(
+            0..0,
+            [
+                Segment::eci(UTF8_ECI).unwrap(),
+                Segment::eci(UTF8_ECI).unwrap(),
+            ],
+        );
+        assert_eq!(structured_append(&segments, 1, ECLevel::High), too_long);
+    }
+
+    #[test]
+    fn test_fnc1() {
+        let segments = segment_fnc1("01095011010209171719050810ABCD1234\x1D2110", 1);
+        let data: Vec<u8> = segments.iter().flat_map(|s| s.data.clone()).collect();
+        assert_eq!(data, b"01095011010209171719050810ABCD1234%2110");
+        assert!(segments.iter().all(|s| s.mode != Mode::Byte));
+
+        let segments = segment_fnc1("ABC%DEF", 1);
+        assert_eq!(
+            segments,
+            vec![Segment::new("ABC%%DEF", Mode::Alphanumeric).unwrap()]
+        );
+
+        let (_, segments) =
+            detect_segments("0112345", ECLevel::Low, 1, false, Some(Fnc1::Second(37))).unwrap();
+        assert_eq!(segments[0].mode, Mode::Fnc1Second);
+        assert_eq!(segments[0].bit_len(1), Ok(12));
+        let res = encode(&segments[..1], 1, ECLevel::Low).unwrap();
+        assert_eq!(&res[..2], &[0x92, 0x50]);
+        assert_eq!(Fnc1::First.segment().unwrap().bit_len(1), Ok(4));
+        for app in [0, 99, b'A' + 100, b'Z' + 100, b'a' + 100, b'z' + 100] {
+            assert_eq!(Fnc1::Second(app).segment().unwrap().data, [app]);
+        }
+        for app in [100, 150, b'Z' + 101, 255] {
+            assert_eq!(
+                Fnc1::Second(app).segment(),
+                Err(Error::InvalidApplicationIndicator(app))
+            );
+        }
     }
 
     #[test]
@@ -240,7 +1246,8 @@ mod tests {
                 ],
                 5,
                 ECLevel::Quartile
-            ),
+            )
+            .unwrap(),
             vec![
                 0x41, 0x03, 0x11, 0x11, 0x14, 0x13, 0xEC, 0xEC, 0x86, 0x23, 0x11, 0x11, 0x56, 0x30,
                 0xEC, 0xEC, 0xC6, 0xEC, 0x11, 0x11, 0xC6, 0x11, 0xEC, 0xEC, 0xF2, 0xEC, 0x11, 0x11,
@@ -255,4 +1262,46 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_decode_segments() {
+        let segments = vec![
+            Segment::eci(100000).unwrap(),
+            Segment::new("0123456789", Mode::Numeric).unwrap(),
+            Segment::new("HELLO WORLD", Mode::Alphanumeric).unwrap(),
+            Segment::bytes(&[0x00, 0xFF, 0x80]),
+            Segment::new("点茗", Mode::Kanji).unwrap(),
+        ];
+        let codewords = encode(&segments, 5, ECLevel::Medium).unwrap();
+        let (data, _) = deinterleave_and_correct(&codewords, 5, ECLevel::Medium).unwrap();
+        assert_eq!(decode_segments(&data, 5), Ok(segments));
+
+        assert_eq!(decode_segments(&[0b0110_0000], 1), Err(Error::Malformed));
+        // numeric group over 999
+        assert_eq!(
+            decode_segments(&[0x10, 0x0F, 0xFF, 0xC0], 1),
+            Err(Error::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_deinterleave() {
+        // 2 blocks of 15 and 2 of 16
+        let data: Vec<u8> = (0..62).collect();
+        let mut codewords = interleave_and_ec(&data, 5, ECLevel::Quartile).unwrap();
+        assert_eq!(
+            deinterleave_and_correct(&codewords, 5, ECLevel::Quartile),
+            Ok((data.clone(), 0))
+        );
+        codewords[3] ^= 0xFF;
+        codewords[70] ^= 0x0F;
+        assert_eq!(
+            deinterleave_and_correct(&codewords, 5, ECLevel::Quartile),
+            Ok((data, 2))
+        );
+        assert_eq!(
+            deinterleave_and_correct(&codewords[1..], 5, ECLevel::Quartile),
+            Err(Error::Malformed)
+        );
+    }
 }
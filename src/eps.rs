@@ -0,0 +1,104 @@
+use std::fmt::Write;
+
+use crate::layout::Qr;
+use crate::render::{PrintOptions, dark_runs, format_number, format_rgb};
+
+/// an encapsulated postscript file with its bounding box around the symbol and quiet zone, with
+/// the dark modules as one filled path
+pub fn qr_to_eps(qr: &Qr, options: &PrintOptions) -> String {
+    let size = options.modules(qr);
+    let quiet = options.quiet_zone.unwrap_or(qr.quiet_zone());
+    let points = options.points(qr);
+    let module = format_number(points / size as f64);
+
+    let mut res = String::from("%!PS-Adobe-3.0 EPSF-3.0\n");
+    // the plain bounding box has to be whole points, so it's rounded out
+    writeln!(
+        res,
+        "%%BoundingBox: 0 0 {} {}",
+        points.ceil(),
+        points.ceil()
+    )
+    .unwrap();
+    let points = format_number(points);
+    writeln!(res, "%%HiResBoundingBox: 0 0 {} {}", points, points).unwrap();
+    res.push_str("%%Creator: qr\n%%Pages: 1\n%%EndComments\n");
+
+    // "len x y R" adds a run of len modules to the path
+    res.push_str("/R { moveto dup 0 rlineto 0 1 rlineto neg 0 rlineto closepath } bind def\n");
+    // flip the y axis and scale so the drawing is in modules from the top left, like svg
+    writeln!(
+        res,
+        "gsave\n0 {} translate\n{} -{} scale",
+        points, module, module
+    )
+    .unwrap();
+    if let Some(background) = options.background {
+        writeln!(
+            res,
+            "{} setrgbcolor\n0 0 {} {} rectfill",
+            format_rgb(background),
+            size,
+            size
+        )
+        .unwrap();
+    }
+    writeln!(
+        res,
+        "{} setrgbcolor\nnewpath",
+        format_rgb(options.foreground)
+    )
+    .unwrap();
+    for (x, y, len) in dark_runs(&qr.data) {
+        writeln!(res, "{} {} {} R", len, x + quiet, y + quiet).unwrap();
+    }
+    res.push_str("fill\ngrestore\nshowpage\n%%EOF\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+
+    #[test]
+    fn test_qr_to_eps() {
+        let (qr, _) = QrBuilder::new("hello eps").build().unwrap();
+        let options = PrintOptions::default().fit_size(&qr, 29.0 * 25.4 / 72.0);
+        let eps = qr_to_eps(&qr, &options);
+        assert!(eps.starts_with("%!PS-Adobe-3.0 EPSF-3.0\n%%BoundingBox: 0 0 29 29\n"));
+        assert!(eps.ends_with("%%EOF\n"));
+        assert!(eps.contains("0 29 translate\n1 -1 scale\n1 1 1 setrgbcolor\n"));
+
+        // the modules back out of the runs
+        let mut modules = vec![vec![false; qr.width()]; qr.width()];
+        for line in eps.lines().filter(|l| l.ends_with(" R")) {
+            let run: Vec<usize> = line
+                .split(' ')
+                .take(3)
+                .map(|n| n.parse().unwrap())
+                .collect();
+            let (len, x, y) = (run[0], run[1] - 4, run[2] - 4);
+            modules[y][x..x + len].fill(true);
+        }
+        assert_eq!(modules, qr.data);
+    }
+
+    #[test]
+    fn test_eps_options() {
+        let (qr, _) = QrBuilder::new("hello eps").build().unwrap();
+        let options = PrintOptions {
+            module_mm: 1.0,
+            quiet_zone: Some(2),
+            foreground: [255, 0, 51],
+            background: None,
+        };
+        let eps = qr_to_eps(&qr, &options);
+        // 25 mm is 70.866 points
+        assert!(
+            eps.contains("%%BoundingBox: 0 0 71 71\n%%HiResBoundingBox: 0 0 70.8661 70.8661\n")
+        );
+        assert!(!eps.contains("rectfill"));
+        assert!(eps.contains("1 0 0.2 setrgbcolor\nnewpath\n7 2 2 R\n"));
+    }
+}
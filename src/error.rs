@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::encoding::{ECLevel, Mode};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    /// the data doesn't fit in any allowed version at the chosen error correction level
+    DataTooLong,
+    /// the data doesn't fit in a symbol of this version or smaller at this error correction level
+    DataTooLongForVersion(usize, ECLevel),
+    /// versions go from 1 to 40
+    InvalidVersion(usize),
+    /// the minimum version is above the maximum, so there's nothing to pick from
+    InvalidVersionRange(usize, usize),
+    /// symbols are 21 to 177 modules wide in steps of 4
+    InvalidWidth(usize),
+    /// masks go from 0 to 7
+    InvalidMask(usize),
+    /// format information is 2 bits of error correction level and 3 of mask
+    InvalidFormat(usize),
+    /// the character can't be encoded in the requested mode
+    UnencodableCharacter(char),
+    /// text can't be put in a segment of this mode
+    InvalidMode(Mode),
+    /// eci assignment numbers go up to 999999
+    InvalidEci(u32),
+    /// fnc1 application indicators are 0 to 99 or an ascii letter plus 100
+    InvalidApplicationIndicator(u8),
+    /// structured append can't split data across more than 16 symbols
+    TooManySymbols(usize),
+    /// the position is outside the symbol
+    OutOfBounds((usize, usize)),
+    /// a reed solomon block can be at most 255 codewords long
+    BlockTooLong(usize),
+    /// there were more errors than the error correction codewords can fix
+    Uncorrectable,
+    /// an erasure position past the end of the block
+    InvalidErasure(usize),
+    /// the symbol's data doesn't follow the spec
+    Malformed,
+    /// a symbol didn't decode back to what was encoded
+    VerifyFailed,
+    /// can't make an image with no pixels
+    EmptyImage,
+    /// the image is corrupt or in a format that isn't supported
+    InvalidImage,
+    /// couldn't find a qr code in the image
+    NoCodeFound,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DataTooLong => write!(f, "too much data to fit in a qr code"),
+            Error::DataTooLongForVersion(v, ec) => write!(
+                f,
+                "too much data to fit in a version {} qr code at {:?} error correction",
+                v, ec
+            ),
+            Error::InvalidVersion(v) => write!(f, "invalid version {}, must be 1-40", v),
+            Error::InvalidVersionRange(min, max) => {
+                write!(f, "minimum version {} is above the maximum {}", min, max)
+            }
+            Error::InvalidWidth(w) => write!(f, "invalid symbol width {}", w),
+            Error::InvalidMask(m) => write!(f, "invalid mask {}, must be 0-7", m),
+            Error::InvalidFormat(n) => write!(f, "invalid format information {:#b}", n),
+            Error::UnencodableCharacter(c) => {
+                write!(f, "character {:?} can't be encoded in this mode", c)
+            }
+            Error::InvalidMode(m) => write!(f, "can't make a {:?} segment from text", m),
+            Error::InvalidEci(n) => write!(f, "invalid eci assignment number {}", n),
+            Error::InvalidApplicationIndicator(n) => write!(
+                f,
+                "invalid fnc1 application indicator {}, must be 0-99 or an ascii letter + 100",
+                n
+            ),
+            Error::TooManySymbols(n) => {
+                write!(f, "structured append needs {} symbols but max is 16", n)
+            }
+            Error::OutOfBounds(p) => write!(f, "position ({}, {}) is out of bounds", p.0, p.1),
+            Error::BlockTooLong(n) => write!(f, "block was {} codewords but max is 255", n),
+            Error::Uncorrectable => write!(f, "too many errors to correct"),
+            Error::InvalidErasure(p) => write!(f, "erasure position {} is outside the block", p),
+            Error::Malformed => write!(f, "the symbol's data is malformed"),
+            Error::VerifyFailed => write!(f, "the symbol didn't decode back to the same data"),
+            Error::EmptyImage => write!(f, "tried to make an empty image"),
+            Error::InvalidImage => write!(f, "the image is corrupt or not a supported format"),
+            Error::NoCodeFound => write!(f, "couldn't find a qr code in the image"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,6 +1,7 @@
-use std::iter;
+use std::{fmt, iter};
 
 use crate::encoding::{self, ECLevel};
+use crate::error::Error;
 use crate::tables::{ALIGNMENT_PATTERNS, VERSION_INFO};
 use crate::{bitstream, rsec};
 
@@ -15,6 +16,9 @@ const MASKS: [fn((usize, usize)) -> bool; 8] = [
     |p| ((p.0 + p.1) % 2 + (p.0 * p.1) % 3) % 2 == 0,
 ];
 
+/// quiet zone width in modules the spec asks for
+pub const DEFAULT_QUIET_ZONE: usize = 4;
+
 const FINDER_LIKE_LEN: usize = 11;
 const FINDER_LIKE: [[bool; FINDER_LIKE_LEN]; 2] = [
     [
@@ -30,51 +34,132 @@ pub struct Qr {
     pub data: Vec<Vec<bool>>,
     version: usize,
     ec: ECLevel,
+    mask: Option<usize>,
+    segments: Vec<encoding::Segment>,
+    quiet_zone: usize,
 }
 
-impl Qr {
-    pub fn make_blank(version: usize, ec: ECLevel) -> Self {
-        if !(1..=40).contains(&version) {
-            panic!("tried to make qr code with invalid version!");
+/// What went into making a qr code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeReport {
+    pub modes: Vec<encoding::Mode>,
+    pub version: usize,
+    pub ec: ECLevel,
+    /// the ec level asked for, lower than `ec` if it was boosted
+    pub requested_ec: ECLevel,
+    pub mask: usize,
+    /// penalty score of every mask, the lowest one gets picked unless the mask was forced
+    pub mask_scores: [usize; 8],
+    /// bits used by the segments before the terminator and padding
+    pub data_bits: usize,
+    pub data_codewords: usize,
+    pub ec_codewords: usize,
+}
+
+impl fmt::Display for EncodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ec != self.requested_ec {
+            writeln!(
+                f,
+                "ec level: {:?} (boosted from {:?})",
+                self.ec, self.requested_ec
+            )?;
+        } else {
+            writeln!(f, "ec level: {:?}", self.ec)?;
         }
-        Self {
-            data: make_fixed_patterns(version).unwrap(),
+        writeln!(f, "modes: {:?}", self.modes)?;
+        writeln!(f, "version: {}", self.version)?;
+        writeln!(f, "mask: {}", self.mask)?;
+        writeln!(f, "mask scores: {:?}", self.mask_scores)?;
+        writeln!(
+            f,
+            "data: {} bits in {} codewords",
+            self.data_bits, self.data_codewords
+        )?;
+        write!(f, "ec codewords: {}", self.ec_codewords)
+    }
+}
+
+impl Qr {
+    pub fn make_blank(version: usize, ec: ECLevel) -> Result<Self, Error> {
+        Ok(Self {
+            data: make_fixed_patterns(version)?,
             version,
             ec,
-        }
+            mask: None,
+            segments: vec![],
+            quiet_zone: DEFAULT_QUIET_ZONE,
+        })
+    }
+
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    pub fn ec(&self) -> ECLevel {
+        self.ec
+    }
+
+    /// the applied mask, None for blank codes
+    pub fn mask(&self) -> Option<usize> {
+        self.mask
+    }
+
+    /// width (and height) in modules, not including the quiet zone
+    pub fn width(&self) -> usize {
+        self.data.len()
+    }
+
+    /// is the module in column x and row y dark? None if it's outside the code
+    pub fn get(&self, x: usize, y: usize) -> Option<bool> {
+        self.data.get(y)?.get(x).copied()
+    }
+
+    /// the segments encoded in this code, empty for blank codes
+    pub fn segments(&self) -> &[encoding::Segment] {
+        &self.segments
     }
 
-    pub fn make_qr(
-        data: &str,
-        ec: Option<ECLevel>,
+    /// width of the blank border in modules
+    pub fn quiet_zone(&self) -> usize {
+        self.quiet_zone
+    }
+
+    pub(crate) fn set_quiet_zone(&mut self, modules: usize) {
+        self.quiet_zone = modules;
+    }
+
+    pub(crate) fn from_segments(
+        segments: &[encoding::Segment],
+        version: usize,
+        ec: ECLevel,
         mask: Option<usize>,
-        min_version: Option<usize>,
-    ) -> Option<Self> {
-        let ec = ec.unwrap_or(ECLevel::Low);
-        println!("ec level: {:?}", ec);
-        let min_version = min_version.unwrap_or(0);
-        // encode data
-        let mode = encoding::detect_mode(data);
-        println!("mode: {:?}", mode);
-        // need a better length calculation for the other modes but it works for now
-        let version = encoding::detect_version(mode, encoding::data_len(mode, data.len()), ec)
-            .expect("too much data")
-            .max(min_version);
-        println!("version: {:?}", version);
-        let encoded = encoding::encode(data, mode, version, ec).unwrap();
-        // println!("encoded: {:02X?} len: {}", encoded, encoded.len());
+    ) -> Result<(Self, EncodeReport), Error> {
+        let encoded = encoding::encode(segments, version, ec)?;
         let stream: Vec<bool> = bitstream::Bitstream::from_bytes(&encoded).into();
 
         // draw qr code
-        let mut qr = Self::make_blank(version, ec);
-        let order = ModuleOrder::new(version);
+        let mut qr = Self::make_blank(version, ec)?;
+        qr.segments = segments.to_vec();
+        let order = ModuleOrder::new(version)?;
         stream
             .iter()
             .zip(order)
             .for_each(|(bit, pos)| qr.data[pos.0][pos.1] = *bit);
 
-        qr = apply_best_mask(&qr, mask);
-        Some(qr)
+        let (qr, mask, mask_scores) = apply_best_mask(&qr, mask)?;
+        let report = EncodeReport {
+            modes: segments.iter().map(|s| s.mode).collect(),
+            version,
+            ec,
+            requested_ec: ec,
+            mask,
+            mask_scores,
+            data_bits: encoding::bit_len(segments, version)?,
+            data_codewords: encoding::data_codewords(version, ec)?,
+            ec_codewords: encoded.len() - encoding::data_codewords(version, ec)?,
+        };
+        Ok((qr, report))
     }
 
     pub fn score(&self) -> usize {
@@ -82,15 +167,16 @@ impl Qr {
     }
 }
 
-pub fn version_to_width(version: usize) -> Option<usize> {
+pub fn version_to_width(version: usize) -> Result<usize, Error> {
     if !(1..=40).contains(&version) {
-        None
+        Err(Error::InvalidVersion(version))
     } else {
-        Some((version * 4) + 17)
+        Ok((version * 4) + 17)
     }
 }
 
-pub fn get_alignment_locations(version: usize) -> Vec<(usize, usize)> {
+pub fn get_alignment_locations(version: usize) -> Result<Vec<(usize, usize)>, Error> {
+    version_to_width(version)?;
     let channels = ALIGNMENT_PATTERNS[version - 1];
     let max = channels.last().unwrap_or(&0); // 0 is just a dummy value here
     let mut res = vec![];
@@ -105,13 +191,10 @@ pub fn get_alignment_locations(version: usize) -> Vec<(usize, usize)> {
         }
     }
 
-    res
+    Ok(res)
 }
 
-pub fn make_fixed_patterns(version: usize) -> Option<Vec<Vec<bool>>> {
-    if !(1..=40).contains(&version) {
-        return None;
-    }
+pub fn make_fixed_patterns(version: usize) -> Result<Vec<Vec<bool>>, Error> {
     let max = version_to_width(version)?;
 
     let mut res: Vec<Vec<bool>> =
@@ -121,8 +204,8 @@ pub fn make_fixed_patterns(version: usize) -> Option<Vec<Vec<bool>>> {
     for (row, module) in res.iter_mut().enumerate() {
         module[6] = row & 1 == 0;
     }
-    for col in 0..max {
-        res[6][col] = col & 1 == 0;
+    for (col, module) in res[6].iter_mut().enumerate() {
+        *module = col & 1 == 0;
     }
 
     // draw finders
@@ -131,7 +214,7 @@ pub fn make_fixed_patterns(version: usize) -> Option<Vec<Vec<bool>>> {
     draw_finder(&mut res, (max - 4, 3));
 
     // draw alignment patterns
-    for pos in get_alignment_locations(version) {
+    for pos in get_alignment_locations(version)? {
         draw_alignment(&mut res, pos);
     }
 
@@ -141,7 +224,7 @@ pub fn make_fixed_patterns(version: usize) -> Option<Vec<Vec<bool>>> {
     // draw the version patterns
     draw_version(&mut res, version);
 
-    Some(res)
+    Ok(res)
 }
 
 pub fn draw_square(
@@ -177,14 +260,17 @@ fn draw_number(data: &mut [Vec<bool>], num: usize, coords: &[(usize, usize)]) {
     }
 }
 
-fn draw_format(qr: &mut Qr, mask: usize) {
-    let form = rsec::qr_format_encode_masked(((qr.ec as usize) << 3) | mask);
-    let max = version_to_width(qr.version).unwrap() - 1;
+fn read_number(data: &[Vec<bool>], coords: &[(usize, usize)]) -> usize {
+    coords
+        .iter()
+        .fold(0, |acc, pos| (acc << 1) | data[pos.0][pos.1] as usize)
+}
 
-    draw_number(
-        &mut qr.data,
-        form,
-        &[
+// both copies of the format information, most significant bit first
+fn format_coords(width: usize) -> [[(usize, usize); 15]; 2] {
+    let max = width - 1;
+    [
+        [
             (8, 0),
             (8, 1),
             (8, 2),
@@ -201,11 +287,7 @@ fn draw_format(qr: &mut Qr, mask: usize) {
             (1, 8),
             (0, 8),
         ],
-    );
-    draw_number(
-        &mut qr.data,
-        form,
-        &[
+        [
             (max, 8),
             (max - 1, 8),
             (max - 2, 8),
@@ -222,71 +304,102 @@ fn draw_format(qr: &mut Qr, mask: usize) {
             (8, max - 1),
             (8, max),
         ],
-    );
+    ]
+}
+
+// both copies of the version information, most significant bit first
+fn version_coords(width: usize) -> [[(usize, usize); 18]; 2] {
+    let top_right = std::array::from_fn(|i| (width - 9 - i % 3, 5 - i / 3));
+    [top_right, top_right.map(|(row, col)| (col, row))]
+}
+
+fn draw_format(qr: &mut Qr, mask: usize) -> Result<(), Error> {
+    if mask > 7 {
+        return Err(Error::InvalidMask(mask));
+    }
+    let form = rsec::qr_format_encode_masked(((qr.ec as usize) << 3) | mask)?;
+    for coords in format_coords(qr.data.len()) {
+        draw_number(&mut qr.data, form, &coords);
+    }
+    Ok(())
 }
 
 pub fn draw_version(data: &mut [Vec<bool>], version: usize) {
     if !(7..=40).contains(&version) {
         return;
     }
-    let max = version_to_width(version).unwrap();
-    let ver = VERSION_INFO[version - 1];
-
-    draw_number(
-        data,
-        ver,
-        &[
-            (max - 9, 5),
-            (max - 10, 5),
-            (max - 11, 5),
-            (max - 9, 4),
-            (max - 10, 4),
-            (max - 11, 4),
-            (max - 9, 3),
-            (max - 10, 3),
-            (max - 11, 3),
-            (max - 9, 2),
-            (max - 10, 2),
-            (max - 11, 2),
-            (max - 9, 1),
-            (max - 10, 1),
-            (max - 11, 1),
-            (max - 9, 0),
-            (max - 10, 0),
-            (max - 11, 0),
-        ],
-    );
-
-    draw_number(
-        data,
-        ver,
-        &[
-            (5, max - 9),
-            (5, max - 10),
-            (5, max - 11),
-            (4, max - 9),
-            (4, max - 10),
-            (4, max - 11),
-            (3, max - 9),
-            (3, max - 10),
-            (3, max - 11),
-            (2, max - 9),
-            (2, max - 10),
-            (2, max - 11),
-            (1, max - 9),
-            (1, max - 10),
-            (1, max - 11),
-            (0, max - 9),
-            (0, max - 10),
-            (0, max - 11),
-        ],
-    );
+    for coords in version_coords(data.len()) {
+        draw_number(data, VERSION_INFO[version - 1], &coords);
+    }
 }
 
-pub fn is_alignment_pattern(version: usize, pos: (usize, usize)) -> bool {
-    if !(1..=40).contains(&version) {
-        panic!("invalid version!")
+/// the version a symbol of this width would be
+pub fn width_to_version(width: usize) -> Result<usize, Error> {
+    if !(21..=177).contains(&width) || !(width - 17).is_multiple_of(4) {
+        Err(Error::InvalidWidth(width))
+    } else {
+        Ok((width - 17) / 4)
     }
+}
+
+pub(crate) fn check_square(data: &[Vec<bool>]) -> Result<usize, Error> {
+    let version = width_to_version(data.len())?;
+    match data.iter().find(|row| row.len() != data.len()) {
+        Some(row) => Err(Error::InvalidWidth(row.len())),
+        None => Ok(version),
+    }
+}
+
+/// reads both copies of the format information and returns the ec level and mask from whichever
+/// is closer to a valid one, along with how many bits were wrong
+pub fn read_format(data: &[Vec<bool>]) -> Result<(ECLevel, usize, u32), Error> {
+    check_square(data)?;
+    let (ec, mask, distance) = format_coords(data.len())
+        .iter()
+        .map(|coords| rsec::qr_format_decode_masked(read_number(data, coords)))
+        .min_by_key(|(_, _, distance)| *distance)
+        .unwrap();
+    if distance > rsec::MAX_BCH_ERRORS {
+        return Err(Error::Uncorrectable);
+    }
+    Ok((ec, mask as usize, distance))
+}
+
+/// the codewords in the data modules in placement order, with the mask undone
+pub fn read_codewords(data: &[Vec<bool>], mask: usize) -> Result<Vec<u8>, Error> {
+    let version = check_square(data)?;
+    let mask_fn = MASKS.get(mask).ok_or(Error::InvalidMask(mask))?;
+    let bits: Vec<bool> = ModuleOrder::new(version)?
+        .map(|pos| data[pos.0][pos.1] != mask_fn(pos))
+        .collect();
+    // the remainder bits don't make a whole codeword
+    Ok(bits
+        .chunks_exact(8)
+        .map(|byte| byte.iter().fold(0, |acc, bit| (acc << 1) | *bit as u8))
+        .collect())
+}
+
+/// reads both copies of the version information and returns the version from whichever is closer
+/// to a valid one, along with how many bits were wrong. versions below 7 don't have version
+/// information so it comes from the width
+pub fn read_version(data: &[Vec<bool>]) -> Result<(usize, u32), Error> {
+    let version = check_square(data)?;
+    if version < 7 {
+        return Ok((version, 0));
+    }
+    let (version, distance) = version_coords(data.len())
+        .iter()
+        .map(|coords| rsec::qr_version_decode(read_number(data, coords)))
+        .min_by_key(|(_, distance)| *distance)
+        .unwrap();
+    if distance > rsec::MAX_BCH_ERRORS {
+        return Err(Error::Uncorrectable);
+    }
+    Ok((version, distance))
+}
+
+pub fn is_alignment_pattern(version: usize, pos: (usize, usize)) -> Result<bool, Error> {
+    version_to_width(version)?;
     let coords = ALIGNMENT_PATTERNS[version - 1];
     let max = coords.last().unwrap_or(&0);
     for i in coords {
@@ -296,12 +409,12 @@ pub fn is_alignment_pattern(version: usize, pos: (usize, usize)) -> bool {
                 continue;
             }
             if i.abs_diff(pos.0) < 3 && j.abs_diff(pos.1) < 3 {
-                return true;
+                return Ok(true);
             }
         }
     }
 
-    false
+    Ok(false)
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -315,16 +428,11 @@ pub enum ModuleType {
     Data,
 }
 
-/// i thought this would be useful but it hasn't been so far, maybe there will be a use?
-pub fn module_type(version: usize, pos: (usize, usize)) -> ModuleType {
-    // just kinda give up on invalid ones sorry
-    if !(1..=40).contains(&version) {
-        panic!("invalid version!")
-    }
-
-    let max = version_to_width(version).unwrap();
+/// what a module is used for
+pub fn module_type(version: usize, pos: (usize, usize)) -> Result<ModuleType, Error> {
+    let max = version_to_width(version)?;
     if pos.0 >= max || pos.1 >= max {
-        panic!("out of bounds! {} ({}, {})", max, pos.0, pos.1)
+        return Err(Error::OutOfBounds(pos));
     }
 
     // finder patterns
@@ -332,22 +440,22 @@ pub fn module_type(version: usize, pos: (usize, usize)) -> ModuleType {
         || ((0..=7).contains(&pos.0) && ((max - 8)..=(max - 1)).contains(&pos.1))
         || ((max - 8)..=(max - 1)).contains(&pos.0) && ((0..=7).contains(&pos.1))
     {
-        return ModuleType::Finder;
+        return Ok(ModuleType::Finder);
     }
 
     // alignment patterns
-    if is_alignment_pattern(version, pos) {
-        return ModuleType::Alignment;
+    if is_alignment_pattern(version, pos)? {
+        return Ok(ModuleType::Alignment);
     }
 
     // timing patterns
     if pos.0 == 6 || pos.1 == 6 {
-        return ModuleType::Timing;
+        return Ok(ModuleType::Timing);
     }
 
     // that one pixel
     if pos.0 == max - 8 && pos.1 == 8 {
-        return ModuleType::Pixel;
+        return Ok(ModuleType::Pixel);
     }
 
     // version info for versions > 6
@@ -355,22 +463,22 @@ pub fn module_type(version: usize, pos: (usize, usize)) -> ModuleType {
         && ((((max - 11)..=(max - 9)).contains(&pos.0) && (0..=5).contains(&pos.1))
             || (((0..=5).contains(&pos.0)) && ((max - 11)..=(max - 9)).contains(&pos.1)))
     {
-        return ModuleType::Version;
+        return Ok(ModuleType::Version);
     }
 
     // format info
     if (pos.0 == 8 && ((0..=8).contains(&pos.1) || ((max - 8)..=(max - 1)).contains(&pos.1)))
         || (pos.1 == 8 && ((0..=8).contains(&pos.0) || ((max - 8)..=(max - 1)).contains(&pos.0)))
     {
-        return ModuleType::Format;
+        return Ok(ModuleType::Format);
     }
 
-    ModuleType::Data
+    Ok(ModuleType::Data)
 }
 
 /// is this postition a data module for the given version?
-pub fn is_data_module(version: usize, pos: (usize, usize)) -> bool {
-    module_type(version, pos) == ModuleType::Data
+pub fn is_data_module(version: usize, pos: (usize, usize)) -> Result<bool, Error> {
+    Ok(module_type(version, pos)? == ModuleType::Data)
 }
 
 pub struct ModuleOrder {
@@ -380,16 +488,13 @@ pub struct ModuleOrder {
 }
 
 impl ModuleOrder {
-    pub fn new(version: usize) -> Self {
-        if !(1..=40).contains(&version) {
-            panic!("invalid version!")
-        }
-        let max = version_to_width(version).unwrap() - 1;
-        Self {
+    pub fn new(version: usize) -> Result<Self, Error> {
+        let max = version_to_width(version)? - 1;
+        Ok(Self {
             curr: (max, max),
             version,
             done: false,
-        }
+        })
     }
 }
 
@@ -400,7 +505,7 @@ impl Iterator for ModuleOrder {
         if self.done {
             return None;
         }
-        let max = version_to_width(self.version)? - 1;
+        let max = version_to_width(self.version).ok()? - 1;
         let res = self.curr;
         let mut curr = self.curr;
         loop {
@@ -436,7 +541,7 @@ impl Iterator for ModuleOrder {
             if curr.1 == 6 {
                 curr = (curr.0, curr.1 - 1);
             }
-            if is_data_module(self.version, curr) {
+            if is_data_module(self.version, curr).ok()? {
                 self.curr = curr;
                 return Some(res);
             }
@@ -444,27 +549,32 @@ impl Iterator for ModuleOrder {
     }
 }
 
-fn apply_best_mask(qr: &Qr, mask: Option<usize>) -> Qr {
-    let mut choices = (0..=7).map(|n| apply_mask(qr, n));
-    if let Some(mask_choice) = mask {
-        return choices.nth(mask_choice).unwrap();
-    }
-    let res = choices.min_by_key(Qr::score).unwrap();
-    println!("score: {}", res.score());
-    res
+/// returns the masked qr code, which mask was used and the scores for all of them
+fn apply_best_mask(qr: &Qr, mask: Option<usize>) -> Result<(Qr, usize, [usize; 8]), Error> {
+    let mut choices: Vec<Qr> = (0..=7)
+        .map(|n| apply_mask(qr, n))
+        .collect::<Result<_, _>>()?;
+    let scores: [usize; 8] = std::array::from_fn(|n| choices[n].score());
+    let mask = match mask {
+        Some(mask_choice) if mask_choice > 7 => return Err(Error::InvalidMask(mask_choice)),
+        Some(mask_choice) => mask_choice,
+        None => (0..=7).min_by_key(|n| scores[*n]).unwrap(),
+    };
+    Ok((choices.swap_remove(mask), mask, scores))
 }
 
-fn apply_mask(qr: &Qr, mask: usize) -> Qr {
+fn apply_mask(qr: &Qr, mask: usize) -> Result<Qr, Error> {
     let mut res = qr.clone();
-    draw_format(&mut res, mask);
+    res.mask = Some(mask);
+    draw_format(&mut res, mask)?;
     for (i, row) in res.data.iter_mut().enumerate() {
         for (j, module) in row.iter_mut().enumerate() {
-            if is_data_module(qr.version, (i, j)) {
+            if is_data_module(qr.version, (i, j)) == Ok(true) {
                 *module = *module != MASKS[mask]((i, j));
             }
         }
     }
-    res
+    Ok(res)
 }
 
 fn score_matrix(data: &[Vec<bool>]) -> usize {
@@ -591,17 +701,121 @@ fn score_matrix(data: &[Vec<bool>]) -> usize {
 
 #[cfg(test)]
 mod tests {
-    use crate::layout::get_alignment_locations;
+    use crate::builder::QrBuilder;
+    use crate::encoding::{ECLevel, Mode};
+    use crate::error::Error;
+    use crate::layout::{
+        ModuleOrder, Qr, get_alignment_locations, module_type, read_format, read_version,
+        width_to_version,
+    };
+
+    #[test]
+    fn test_report() {
+        let (qr, report) = QrBuilder::new("HELLO 123")
+            .ec(ECLevel::Quartile)
+            .build()
+            .unwrap();
+        assert_eq!(report.version, 1);
+        assert_eq!(report.ec, ECLevel::Quartile);
+        assert_eq!(report.modes, vec![Mode::Alphanumeric]);
+        assert_eq!(report.data_bits, 4 + 9 + 50);
+        assert_eq!(report.data_codewords, 13);
+        assert_eq!(report.ec_codewords, 13);
+        assert_eq!(report.mask_scores[report.mask], qr.score());
+        assert_eq!(report.mask_scores.iter().min(), Some(&qr.score()));
+
+        let (_, report) = QrBuilder::new("HELLO 123")
+            .ec(ECLevel::Quartile)
+            .mask(6)
+            .build()
+            .unwrap();
+        assert_eq!(report.mask, 6);
+    }
+
+    #[test]
+    fn test_accessors() {
+        let (qr, report) = QrBuilder::new("HELLO 123").min_version(3).build().unwrap();
+        assert_eq!(qr.version(), 3);
+        assert_eq!(qr.ec(), ECLevel::Low);
+        assert_eq!(qr.mask(), Some(report.mask));
+        assert_eq!(qr.width(), 29);
+        assert_eq!(qr.get(0, 0), Some(true));
+        assert_eq!(qr.get(7, 0), Some(false));
+        assert_eq!(qr.get(29, 0), None);
+        assert_eq!(qr.segments()[0].data, b"HELLO 123");
+
+        let blank = Qr::make_blank(2, ECLevel::High).unwrap();
+        assert_eq!(blank.mask(), None);
+        assert!(blank.segments().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            QrBuilder::new("hello").mask(8).build().unwrap_err(),
+            Error::InvalidMask(8)
+        );
+        assert_eq!(
+            Qr::make_blank(41, ECLevel::Low).unwrap_err(),
+            Error::InvalidVersion(41)
+        );
+        assert_eq!(module_type(1, (21, 0)), Err(Error::OutOfBounds((21, 0))));
+        assert!(ModuleOrder::new(0).is_err());
+    }
+
+    #[test]
+    fn test_read_format() {
+        let (mut qr, _) = QrBuilder::new("hello")
+            .ec(ECLevel::Quartile)
+            .mask(5)
+            .build()
+            .unwrap();
+        assert_eq!(read_format(&qr.data), Ok((ECLevel::Quartile, 5, 0)));
+
+        // wreck the first copy, the second one still reads
+        for col in 0..6 {
+            qr.data[8][col] = !qr.data[8][col];
+        }
+        assert_eq!(read_format(&qr.data), Ok((ECLevel::Quartile, 5, 0)));
+
+        // and a few errors in the second
+        let max = qr.data.len() - 1;
+        qr.data[max][8] = !qr.data[max][8];
+        qr.data[8][max] = !qr.data[8][max];
+        assert_eq!(read_format(&qr.data), Ok((ECLevel::Quartile, 5, 2)));
+    }
+
+    #[test]
+    fn test_read_version() {
+        let (qr, _) = QrBuilder::new("hello").min_version(3).build().unwrap();
+        assert_eq!(read_version(&qr.data), Ok((3, 0)));
+
+        let (mut qr, _) = QrBuilder::new("hello").min_version(32).build().unwrap();
+        assert_eq!(read_version(&qr.data), Ok((32, 0)));
+
+        // top right copy
+        let max = qr.data.len() - 1;
+        for row in 0..3 {
+            qr.data[row][max - 10] = !qr.data[row][max - 10];
+        }
+        assert_eq!(read_version(&qr.data), Ok((32, 0)));
+        // bottom left copy
+        qr.data[max - 9][0] = !qr.data[max - 9][0];
+        assert_eq!(read_version(&qr.data), Ok((32, 1)));
+
+        assert_eq!(width_to_version(22), Err(Error::InvalidWidth(22)));
+        assert_eq!(read_version(&[]), Err(Error::InvalidWidth(0)));
+    }
 
     #[test]
     fn test_alignment_locations_v1() {
-        assert_eq!(get_alignment_locations(1), vec![])
+        assert_eq!(get_alignment_locations(1).unwrap(), vec![])
     }
 
     #[test]
     fn test_alignment_locations_v7() {
         assert_eq!(
-            get_alignment_locations(7),
+            get_alignment_locations(7).unwrap(),
             vec![(6, 22), (22, 6), (22, 22), (22, 38), (38, 22), (38, 38)]
         )
     }
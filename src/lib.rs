@@ -1,6 +1,21 @@
 pub mod bitmap;
 mod bitstream;
+pub mod builder;
+pub mod decode;
+mod deflate;
 pub mod encoding;
+pub mod eps;
+mod error;
 pub mod layout;
+pub mod netpbm;
+pub mod pdf;
+pub mod png;
+pub mod render;
 mod rsec;
+pub mod scan;
+pub mod style;
+pub mod svg;
 mod tables;
+pub mod terminal;
+
+pub use error::Error;
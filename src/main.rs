@@ -1,16 +1,44 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
 
-use qr::{bitmap, encoding::ECLevel, layout};
+use qr::{
+    bitmap::{self, BmpDepth},
+    builder::QrBuilder,
+    decode,
+    encoding::{ECLevel, Fnc1},
+    eps,
+    layout::{DEFAULT_QUIET_ZONE, Qr},
+    netpbm, pdf, png,
+    render::{PrintOptions, RenderOptions},
+    style::{EyeStyle, ModuleStyle, Style},
+    svg::{self, SvgOptions},
+    terminal::{self, TextStyle},
+};
 
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Message to encode
-    message: String,
+    #[arg(required_unless_present = "input")]
+    message: Option<String>,
+
+    /// Read the message as raw bytes from a file, or stdin if "-"
+    #[arg(short, long, conflicts_with = "message")]
+    input: Option<String>,
 
-    /// Set Error Correction level
+    /// Set minimum Error Correction level
     #[arg(short, long, value_enum)]
     ec: Option<ECLevel>,
 
+    /// Don't raise the Error Correction level when a higher one fits in the same version
+    #[arg(long)]
+    no_boost_ec: bool,
+
     /// Force mask pattern [0-7]
     #[arg(short, long, value_parser = clap::value_parser!(u64).range(0..=7))]
     mask: Option<u64>,
@@ -19,20 +47,352 @@ struct Args {
     #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..=40))]
     version: Option<u64>,
 
-    /// Output path
+    /// Fail instead of using a version above this [1-40]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=40))]
+    max_version: Option<u64>,
+
+    /// Only use this version [1-40]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=40), conflicts_with_all = ["version", "max_version"])]
+    exact_version: Option<u64>,
+
+    /// Use the highest Error Correction level that fits within the max version
+    #[arg(long)]
+    fit: bool,
+
+    /// Declare UTF-8 with an ECI segment when the message isn't plain ASCII
+    #[arg(long)]
+    utf8_eci: bool,
+
+    /// Mark the message as GS1 data (FNC1 in the first position), use ASCII GS (0x1D) to end
+    /// variable length fields
+    #[arg(long, conflicts_with = "input")]
+    gs1: bool,
+
+    /// Mark the message as FNC1 second position data with this application indicator
+    /// (0-99, or an ASCII letter's code + 100)
+    #[arg(long, value_parser = parse_application_indicator, conflicts_with_all = ["input", "gs1"])]
+    fnc1_second: Option<u8>,
+
+    /// Width of the blank border in modules
+    #[arg(short, long, default_value_t = DEFAULT_QUIET_ZONE)]
+    quiet_zone: usize,
+
+    /// Decode each code after making it and fail if it doesn't read back the same
+    #[arg(long)]
+    verify: bool,
+
+    /// Print details about how the code was made to stderr
+    #[arg(long)]
+    verbose: bool,
+
+    /// Output path, the extension picks the format (bmp, png, svg, pbm, pdf or eps)
     #[arg(short, long, default_value_t = String::from("output.bmp"))]
     output: String,
+
+    /// Output format, instead of going by the extension. terminal prints to stdout
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// How to draw the code with --format terminal
+    #[arg(long, value_enum, default_value_t = TextStyle::HalfBlocks)]
+    text_style: TextStyle,
+
+    /// Swap dark and light with --format terminal, for light text on a dark background. ansi
+    /// output sets its own colours and ignores this
+    #[arg(long)]
+    invert: bool,
+
+    /// Width of a module in pixels
+    #[arg(short, long, default_value_t = 4, value_parser = clap::value_parser!(u64).range(1..))]
+    scale: u64,
+
+    /// Pick the biggest scale that keeps the image within this many millimetres at --dpi, pdf and
+    /// eps are made exactly this size
+    #[arg(long, conflicts_with = "scale")]
+    size_mm: Option<f64>,
+
+    /// Resolution to write in the image
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u32).range(1..))]
+    dpi: u32,
+
+    /// Colour of dark modules as RRGGBB or RRGGBBAA hex
+    #[arg(long, value_parser = parse_colour, default_value = "000000")]
+    foreground: [u8; 4],
+
+    /// Colour of light modules and the quiet zone as RRGGBB or RRGGBBAA hex
+    #[arg(long, value_parser = parse_colour, default_value = "ffffff")]
+    background: [u8; 4],
+
+    /// Shape of the modules in images and svgs, timing and alignment patterns stay square
+    #[arg(long, value_enum, default_value_t = ModuleStyle::Square)]
+    module_style: ModuleStyle,
+
+    /// Shape of the outer ring of the finder patterns
+    #[arg(long, value_enum, default_value_t = EyeStyle::Square)]
+    eye_ring: EyeStyle,
+
+    /// Shape of the middle of the finder patterns
+    #[arg(long, value_enum, default_value_t = EyeStyle::Square)]
+    eye_pupil: EyeStyle,
+
+    /// Bits per pixel of bmp images
+    #[arg(long, value_enum, default_value_t = BmpDepth::One)]
+    bmp_depth: BmpDepth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Bmp,
+    Png,
+    Svg,
+    /// Packed P4 netpbm
+    Pbm,
+    /// Ascii P1 netpbm
+    PlainPbm,
+    /// Single page pdf sized to the code
+    Pdf,
+    /// Encapsulated postscript
+    Eps,
+    Terminal,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Read a qr code from a BMP or netpbm image and print its message
+    Decode {
+        /// Image to read
+        path: String,
+
+        /// Print details about the code to stderr
+        #[arg(long)]
+        verbose: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(Command::Decode { path, verbose }) = &args.command {
+        return decode_file(path, *verbose);
+    }
+
+    let mut builder = match (&args.message, &args.input) {
+        (Some(message), _) => QrBuilder::new(message),
+        (None, Some(input)) => QrBuilder::from_bytes(&read_input(input)?),
+        (None, None) => unreachable!(),
+    };
+    if let Some(ec) = args.ec {
+        builder = builder.ec(ec);
+    }
+    if let Some(mask) = args.mask {
+        builder = builder.mask(mask as usize);
+    }
+    if let Some(version) = args.version {
+        builder = builder.min_version(version as usize);
+    }
+    if let Some(version) = args.max_version {
+        builder = builder.max_version(version as usize);
+    }
+    if let Some(version) = args.exact_version {
+        builder = builder.version(version as usize);
+    }
+    if args.gs1 {
+        builder = builder.fnc1(Fnc1::First);
+    } else if let Some(app) = args.fnc1_second {
+        builder = builder.fnc1(Fnc1::Second(app));
+    }
+    builder = builder
+        .utf8_eci(args.utf8_eci)
+        .boost_ec(!args.no_boost_ec)
+        .fit(args.fit)
+        .verify(args.verify)
+        .quiet_zone(args.quiet_zone);
+    // a version limit means a fixed print area, so don't split across several codes
+    let res = if args.max_version.is_some() || args.exact_version.is_some() {
+        vec![builder.build()?]
+    } else {
+        builder.build_split()?
+    };
+    if args.verbose {
+        for (i, (_, report)) in res.iter().enumerate() {
+            if res.len() > 1 {
+                eprintln!("symbol {} of {}", i + 1, res.len());
+            }
+            eprintln!("{}", report);
+        }
+    }
+
+    let format = match args.format {
+        Some(format) => format,
+        None => format_from_path(&args.output)?,
+    };
+    if format == Format::Terminal {
+        for (qr, _) in &res {
+            std::io::stdout().write_all(&render(qr, format, &args)?)?;
+        }
+        return Ok(());
+    }
+
+    if res.len() == 1 {
+        std::fs::write(&args.output, render(&res[0].0, format, &args)?)?;
+        return Ok(());
+    }
+
+    // structured append, number the files
+    let path = Path::new(&args.output);
+    for (i, (qr, _)) in res.iter().enumerate() {
+        let mut name = path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!("-{}", i + 1));
+        if let Some(ext) = path.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+        std::fs::write(path.with_file_name(name), render(qr, format, &args)?)?;
+    }
+    Ok(())
+}
+
+fn format_from_path(path: &str) -> Result<Format, Box<dyn std::error::Error>> {
+    let extension = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("bmp") => Ok(Format::Bmp),
+        Some("png") => Ok(Format::Png),
+        Some("svg") => Ok(Format::Svg),
+        Some("pbm") => Ok(Format::Pbm),
+        Some("pdf") => Ok(Format::Pdf),
+        Some("eps") => Ok(Format::Eps),
+        _ => Err(format!(
+            "can't tell the image format of \"{}\", use .bmp, .png, .svg, .pbm, .pdf or .eps or set \
+             --format",
+            path
+        )
+        .into()),
+    }
+}
+
+fn render(qr: &Qr, format: Format, args: &Args) -> Result<Vec<u8>, qr::Error> {
+    if matches!(format, Format::Pdf | Format::Eps) {
+        return Ok(render_print(qr, format, args));
+    }
+    let mut options = RenderOptions {
+        scale: args.scale as usize,
+        foreground: args.foreground,
+        background: args.background,
+        dpi: args.dpi,
+        style: Style {
+            modules: args.module_style,
+            eye_ring: args.eye_ring,
+            eye_pupil: args.eye_pupil,
+        },
+        ..Default::default()
+    };
+    if let Some(mm) = args.size_mm {
+        options = options.fit_size(qr, mm);
+    }
+    match format {
+        Format::Bmp => bitmap::qr_to_bitmap_with(qr, &options, args.bmp_depth),
+        Format::Png => png::qr_to_png_with(qr, &options),
+        Format::Svg => {
+            let svg_options = SvgOptions {
+                module_size: options.scale as f64,
+                dark: css_colour(options.foreground),
+                light: (options.background[3] != 0).then(|| css_colour(options.background)),
+                style: options.style,
+                ..Default::default()
+            };
+            Ok(svg::qr_to_svg(qr, &svg_options).into_bytes())
+        }
+        Format::Pbm => netpbm::qr_to_pbm(qr, &options, false),
+        Format::PlainPbm => netpbm::qr_to_pbm(qr, &options, true),
+        Format::Pdf | Format::Eps => unreachable!(),
+        Format::Terminal => Ok(terminal::qr_to_text(qr, args.text_style, args.invert).into_bytes()),
+    }
+}
+
+// vector formats for print, a module is --scale pixels at --dpi unless there's a --size-mm
+fn render_print(qr: &Qr, format: Format, args: &Args) -> Vec<u8> {
+    let [fr, fg, fb, _] = args.foreground;
+    let [br, bg, bb, ba] = args.background;
+    let mut options = PrintOptions {
+        module_mm: args.scale as f64 * 25.4 / args.dpi as f64,
+        foreground: [fr, fg, fb],
+        background: (ba != 0).then_some([br, bg, bb]),
+        ..Default::default()
+    };
+    if let Some(mm) = args.size_mm {
+        options = options.fit_size(qr, mm);
+    }
+    match format {
+        Format::Eps => eps::qr_to_eps(qr, &options).into_bytes(),
+        _ => pdf::qr_to_pdf(qr, &options),
+    }
+}
+
+fn parse_colour(s: &str) -> Result<[u8; 4], String> {
+    let hex = s.trim_start_matches('#');
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(String::from("expected RRGGBB or RRGGBBAA"));
+    }
+    // opaque unless there's an alpha channel
+    let mut res = [255; 4];
+    for (i, channel) in res.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|e| e.to_string())?;
+    }
+    Ok(res)
+}
+
+fn parse_application_indicator(s: &str) -> Result<u8, String> {
+    let app: u8 = s
+        .parse()
+        .map_err(|e: std::num::ParseIntError| e.to_string())?;
+    Fnc1::Second(app).segment().map_err(|e| e.to_string())?;
+    Ok(app)
+}
+
+fn css_colour([r, g, b, a]: [u8; 4]) -> String {
+    match a {
+        255 => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        _ => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+    }
+}
+
+fn decode_file(path: &str, verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = std::fs::read(path)?;
+    let image = match bytes.first() {
+        Some(b'P') => netpbm::read_netpbm(&bytes)?,
+        _ => bitmap::read_bitmap(&bytes)?,
+    };
+    let decoded = decode::decode_image(&image)?;
+    if verbose {
+        eprintln!("version: {}", decoded.version);
+        eprintln!("ec level: {:?}", decoded.ec);
+        eprintln!("mask: {}", decoded.mask);
+        eprintln!("codewords corrected: {}", decoded.errors_corrected);
+        if let Some((index, total, _)) = decoded.structured_append() {
+            eprintln!("symbol {} of {}", index + 1, total);
+        }
+    }
+    // raw so binary messages come back out unchanged
+    std::io::stdout().write_all(&decoded.content())?;
+    Ok(())
 }
 
-fn main() {
-    let args = Args::parse();
-    let res = layout::Qr::make_qr(
-        &args.message,
-        args.ec,
-        args.mask.map(|x| x as usize),
-        args.version.map(|x| x as usize),
-    )
-    .unwrap();
-    let bmp = bitmap::qr_to_bitmap(&res).unwrap();
-    std::fs::write(args.output, bmp).unwrap();
+fn read_input(path: &str) -> std::io::Result<Vec<u8>> {
+    if path == "-" {
+        let mut res = vec![];
+        std::io::stdin().read_to_end(&mut res)?;
+        Ok(res)
+    } else {
+        std::fs::read(path)
+    }
 }
@@ -0,0 +1,217 @@
+use crate::bitmap::luminance;
+use crate::error::Error;
+use crate::layout::Qr;
+use crate::render::RenderOptions;
+
+/// a pbm of the symbol, `plain` for the ascii P1 format instead of the packed P4 one. colours
+/// are ignored, pbm is always black on white
+pub fn qr_to_pbm(qr: &Qr, options: &RenderOptions, plain: bool) -> Result<Vec<u8>, Error> {
+    make_pbm(&options.pixels(qr), plain)
+}
+
+/// true is black
+pub fn make_pbm(data: &[Vec<bool>], plain: bool) -> Result<Vec<u8>, Error> {
+    if data.is_empty() || data[0].is_empty() {
+        return Err(Error::EmptyImage);
+    }
+    let (width, height) = (data[0].len(), data.len());
+    let magic = if plain { "P1" } else { "P4" };
+    let mut res = format!("{}\n{} {}\n", magic, width, height).into_bytes();
+    for row in data {
+        if plain {
+            // lines are meant to stay under 70 characters
+            for chunk in row.chunks(35) {
+                let line: Vec<&str> = chunk.iter().map(|&b| if b { "1" } else { "0" }).collect();
+                res.extend(line.join(" ").bytes());
+                res.push(b'\n');
+            }
+        } else {
+            res.extend(row.chunks(8).map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |byte, (i, &dark)| byte | ((dark as u8) << (7 - i)))
+            }));
+        }
+    }
+    Ok(res)
+}
+
+// the numbers in the header, which can have comments between them
+struct Header<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Header<'_> {
+    fn skip_space(&mut self) {
+        while let Some(&b) = self.bytes.get(self.pos) {
+            match b {
+                b'#' => {
+                    while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, Error> {
+        self.skip_space();
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .unwrap()
+            .parse()
+            .map_err(|_| Error::InvalidImage)
+    }
+}
+
+/// reads any pbm, pgm or ppm, plain or raw, into rows of luminance values
+pub fn read_netpbm(bytes: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+    let kind = match bytes {
+        [b'P', kind @ b'1'..=b'6', ..] => kind - b'0',
+        _ => return Err(Error::InvalidImage),
+    };
+    let mut header = Header { bytes, pos: 2 };
+    let (width, height) = (header.number()?, header.number()?);
+    let max = match kind {
+        1 | 4 => 1,
+        _ => header.number()?,
+    };
+    if width == 0 || height == 0 {
+        return Err(Error::EmptyImage);
+    }
+    if max == 0 || max > 65535 {
+        return Err(Error::InvalidImage);
+    }
+    let channels = if kind % 3 == 0 { 3 } else { 1 };
+    let len = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(channels))
+        .ok_or(Error::InvalidImage)?;
+    let scale = |v: usize| (v.min(max) * 255 / max) as u8;
+    let grey = |c: &[u8]| match c {
+        [r, g, b] => luminance(*r, *g, *b),
+        _ => c[0],
+    };
+
+    let samples: Vec<u8> = match kind {
+        // plain bitmaps don't need spaces between pixels
+        1 => bytes[header.pos..]
+            .iter()
+            .filter(|b| matches!(b, b'0' | b'1'))
+            .map(|&b| if b == b'1' { 0 } else { 255 })
+            .collect(),
+        2 | 3 => {
+            let mut res = vec![];
+            for _ in 0..len {
+                res.push(scale(header.number()?));
+            }
+            res
+        }
+        // a single whitespace character, then packed data
+        4 => {
+            let stride = width.div_ceil(8);
+            let data = bytes.get(header.pos + 1..).unwrap_or_default();
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (y, x)))
+                .map(|(y, x)| {
+                    let byte = data.get(y * stride + x / 8).ok_or(Error::InvalidImage)?;
+                    Ok(if (byte >> (7 - x % 8)) & 1 == 1 {
+                        0
+                    } else {
+                        255
+                    })
+                })
+                .collect::<Result<_, Error>>()?
+        }
+        _ => {
+            let data = bytes.get(header.pos + 1..).unwrap_or_default();
+            match max {
+                0..=255 => data.iter().map(|&v| scale(v as usize)).collect(),
+                _ => data
+                    .chunks_exact(2)
+                    .map(|v| scale(u16::from_be_bytes([v[0], v[1]]) as usize))
+                    .collect(),
+            }
+        }
+    };
+    if samples.len() < len {
+        return Err(Error::InvalidImage);
+    }
+    Ok(samples[..len]
+        .chunks(width * channels)
+        .map(|row| row.chunks(channels).map(grey).collect())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+
+    #[test]
+    fn test_pbm() {
+        let data = vec![
+            vec![true, false, true, false, true, false, true, false, true],
+            vec![false; 9],
+        ];
+        let raw = make_pbm(&data, false).unwrap();
+        assert_eq!(raw, b"P4\n9 2\n\xAA\x80\x00\x00");
+        let plain = make_pbm(&data, true).unwrap();
+        assert_eq!(plain, b"P1\n9 2\n1 0 1 0 1 0 1 0 1\n0 0 0 0 0 0 0 0 0\n");
+        let expected: Vec<Vec<u8>> = data
+            .iter()
+            .map(|r| r.iter().map(|&b| if b { 0 } else { 255 }).collect())
+            .collect();
+        assert_eq!(read_netpbm(&raw).unwrap(), expected);
+        assert_eq!(read_netpbm(&plain).unwrap(), expected);
+        assert_eq!(make_pbm(&[], false), Err(Error::EmptyImage));
+    }
+
+    #[test]
+    fn test_read_netpbm() {
+        let cases: [(&[u8], Vec<Vec<u8>>); 6] = [
+            (b"P1\n# comment\n3 1\n101", vec![vec![0, 255, 0]]),
+            (
+                b"P2 2 2 15 0 15\n# in between\n5 10",
+                vec![vec![0, 255], vec![85, 170]],
+            ),
+            (b"P3 1 1 255 255 0 0", vec![vec![76]]),
+            (b"P5 3 1 255\n\x00\x80\xFF", vec![vec![0, 128, 255]]),
+            (b"P5 2 1 65535\n\xFF\xFF\x00\x00", vec![vec![255, 0]]),
+            (b"P6 1 1 255\n\x00\xFF\x00", vec![vec![149]]),
+        ];
+        for (bytes, expected) in cases {
+            assert_eq!(read_netpbm(bytes).unwrap(), expected);
+        }
+        assert_eq!(read_netpbm(b"P5 3 1 255\n\x00"), Err(Error::InvalidImage));
+        assert_eq!(read_netpbm(b"P2 1 1"), Err(Error::InvalidImage));
+        assert_eq!(read_netpbm(b"P7"), Err(Error::InvalidImage));
+        assert_eq!(read_netpbm(b"P4 0 0\n"), Err(Error::EmptyImage));
+    }
+
+    #[test]
+    fn test_qr_to_pbm() {
+        let (qr, _) = QrBuilder::new("netpbm").build().unwrap();
+        let options = RenderOptions {
+            scale: 1,
+            quiet_zone: Some(0),
+            ..Default::default()
+        };
+        for plain in [false, true] {
+            let pbm = qr_to_pbm(&qr, &options, plain).unwrap();
+            let image = read_netpbm(&pbm).unwrap();
+            let modules: Vec<Vec<bool>> = image
+                .iter()
+                .map(|r| r.iter().map(|&v| v == 0).collect())
+                .collect();
+            assert_eq!(modules, qr.data);
+        }
+    }
+}
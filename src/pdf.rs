@@ -0,0 +1,126 @@
+use std::fmt::Write;
+
+use crate::layout::Qr;
+use crate::render::{PrintOptions, dark_runs, format_number, format_rgb};
+
+/// a single page pdf exactly the size of the symbol and its quiet zone, with the dark modules
+/// as one filled path
+pub fn qr_to_pdf(qr: &Qr, options: &PrintOptions) -> Vec<u8> {
+    let size = options.modules(qr);
+    let quiet = options.quiet_zone.unwrap_or(qr.quiet_zone());
+    let points = format_number(options.points(qr));
+    let module = format_number(options.points(qr) / size as f64);
+
+    // flip the y axis and scale so the drawing is in modules from the top left, like svg
+    let mut content = format!("q {} 0 0 -{} 0 {} cm\n", module, module, points);
+    if let Some(background) = options.background {
+        writeln!(
+            content,
+            "{} rg 0 0 {} {} re f",
+            format_rgb(background),
+            size,
+            size
+        )
+        .unwrap();
+    }
+    writeln!(content, "{} rg", format_rgb(options.foreground)).unwrap();
+    for (x, y, len) in dark_runs(&qr.data) {
+        writeln!(content, "{} {} {} 1 re", x + quiet, y + quiet, len).unwrap();
+    }
+    content.push_str("f Q\n");
+
+    let objects = [
+        String::from("<< /Type /Catalog /Pages 2 0 R >>"),
+        String::from("<< /Type /Pages /Kids [3 0 R] /Count 1 >>"),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << >> /Contents 4 0 R >>",
+            points, points
+        ),
+        format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ),
+    ];
+
+    // a comment of high bytes after the header marks the file as binary
+    let mut res = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+    let mut offsets = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(res.len());
+        res.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).bytes());
+    }
+
+    // every cross reference entry is exactly 20 bytes
+    let xref = res.len();
+    let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        writeln!(table, "{:010} 00000 n ", offset).unwrap();
+    }
+    write!(
+        table,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    )
+    .unwrap();
+    res.extend(table.bytes());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+
+    // the binary marker comment comes out as replacement characters
+    fn text(pdf: &[u8]) -> String {
+        String::from_utf8_lossy(pdf).into_owned()
+    }
+
+    #[test]
+    fn test_qr_to_pdf() {
+        let (qr, _) = QrBuilder::new("hello pdf").build().unwrap();
+        let options = PrintOptions::default().fit_size(&qr, 29.0 * 25.4 / 72.0);
+        let bytes = qr_to_pdf(&qr, &options);
+        let pdf = text(&bytes);
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        // one point per module, 29 modules with the quiet zone
+        assert!(pdf.contains("/MediaBox [0 0 29 29]"));
+        assert!(pdf.contains("q 1 0 0 -1 0 29 cm\n1 1 1 rg 0 0 29 29 re f\n0 0 0 rg\n"));
+        assert_eq!(pdf.matches(" re\n").count(), dark_runs(&qr.data).len());
+
+        // the cross reference table points at each object and itself
+        let (_, xref) = pdf.rsplit_once("startxref\n").unwrap();
+        let xref: usize = xref.trim_end_matches("\n%%EOF\n").parse().unwrap();
+        let table = text(&bytes[xref..]);
+        assert!(table.starts_with("xref\n0 5\n"));
+        for (i, entry) in table.lines().skip(3).take(4).enumerate() {
+            assert_eq!(entry.len(), 19);
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(bytes[offset..].starts_with(format!("{} 0 obj\n", i + 1).as_bytes()));
+        }
+
+        // the stream length is what's between the keywords
+        let (_, stream) = pdf.split_once(">>\nstream\n").unwrap();
+        let (stream, _) = stream.split_once("endstream").unwrap();
+        assert!(pdf.contains(&format!("<< /Length {} >>", stream.len())));
+    }
+
+    #[test]
+    fn test_pdf_options() {
+        let (qr, _) = QrBuilder::new("hello pdf").build().unwrap();
+        let options = PrintOptions {
+            module_mm: 1.0,
+            quiet_zone: Some(2),
+            foreground: [255, 0, 51],
+            background: None,
+        };
+        let pdf = text(&qr_to_pdf(&qr, &options));
+        // 25 mm is 70.866 points
+        assert!(pdf.contains("/MediaBox [0 0 70.8661 70.8661]"));
+        assert!(!pdf.contains("re f"));
+        assert!(pdf.contains("cm\n1 0 0.2 rg\n2 2 7 1 re\n"));
+    }
+}
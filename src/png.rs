@@ -0,0 +1,188 @@
+use crate::deflate;
+use crate::error::Error;
+use crate::layout::Qr;
+use crate::render::RenderOptions;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+const fn make_crc_table() -> [u32; 256] {
+    let mut res = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        res[n] = c;
+        n += 1;
+    }
+    res
+}
+
+const CRC_TABLE: [u32; 256] = make_crc_table();
+
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+pub fn qr_to_png(qr: &Qr) -> Result<Vec<u8>, Error> {
+    qr_to_png_with(qr, &RenderOptions::default())
+}
+
+pub fn qr_to_png_with(qr: &Qr, options: &RenderOptions) -> Result<Vec<u8>, Error> {
+    make_png_with(&options.pixels(qr), options)
+}
+
+/// a 1 bit greyscale png, true is black
+pub fn make_png(data: &[Vec<bool>]) -> Result<Vec<u8>, Error> {
+    make_png_with(data, &RenderOptions::default())
+}
+
+/// 1 bit greyscale for opaque black on white, otherwise 1 bit with a two colour palette. only the
+/// colours and dpi of `options` matter here, `data` is already scaled
+pub fn make_png_with(data: &[Vec<bool>], options: &RenderOptions) -> Result<Vec<u8>, Error> {
+    if data.is_empty() || data[0].is_empty() {
+        return Err(Error::EmptyImage);
+    }
+    let (width, height) = (data[0].len(), data.len());
+    let greyscale =
+        options.foreground == [0, 0, 0, 255] && options.background == [255, 255, 255, 255];
+
+    let mut res = SIGNATURE.to_vec();
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.push(1); // bit depth
+    header.push(if greyscale { 0 } else { 3 }); // colour type
+    header.push(0); // deflate
+    header.push(0); // filtering by row
+    header.push(0); // not interlaced
+    write_chunk(&mut res, b"IHDR", &header);
+
+    let mut physical = vec![];
+    physical.extend_from_slice(&options.pixels_per_meter().to_be_bytes());
+    physical.extend_from_slice(&options.pixels_per_meter().to_be_bytes());
+    physical.push(1); // in meters
+    write_chunk(&mut res, b"pHYs", &physical);
+
+    if !greyscale {
+        let [light, dark] = [options.background, options.foreground];
+        write_chunk(&mut res, b"PLTE", &[&light[..3], &dark[..3]].concat());
+        if light[3] != 255 || dark[3] != 255 {
+            write_chunk(&mut res, b"tRNS", &[light[3], dark[3]]);
+        }
+    }
+
+    // every row starts with its filter type, none here, then the pixels 8 to a byte. dark is 0
+    // in greyscale and the second palette entry otherwise
+    let mut pixels = vec![];
+    for row in data {
+        pixels.push(0);
+        pixels.extend(row.chunks(8).map(|chunk| {
+            chunk.iter().enumerate().fold(0u8, |byte, (i, &dark)| {
+                byte | (((dark != greyscale) as u8) << (7 - i))
+            })
+        }));
+    }
+    write_chunk(&mut res, b"IDAT", &deflate::zlib(&pixels));
+    write_chunk(&mut res, b"IEND", &[]);
+
+    Ok(res)
+}
+
+fn write_chunk(res: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    res.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = res.len();
+    res.extend_from_slice(kind);
+    res.extend_from_slice(data);
+    let crc = crc32(&res[start..]);
+    res.extend_from_slice(&crc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+    }
+
+    #[test]
+    fn test_make_png() {
+        let data = vec![
+            vec![true, false, true, false, true, false, true, false, true],
+            vec![false; 9],
+        ];
+        let png = make_png(&data).unwrap();
+        assert_eq!(png[..8], SIGNATURE);
+        // IHDR comes first, 9x2 at 1 bit greyscale
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..29], &[0, 0, 0, 9, 0, 0, 0, 2, 1, 0, 0, 0, 0]);
+        assert_eq!(
+            &png[png.len() - 12..],
+            &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+        assert_eq!(make_png(&[]), Err(Error::EmptyImage));
+    }
+
+    #[test]
+    fn test_qr_to_png() {
+        let (qr, _) = QrBuilder::new(&"a".repeat(1000)).build().unwrap();
+        let png = qr_to_png(&qr).unwrap();
+        // mostly repeated rows, so a lot smaller than one bit per pixel
+        let width = (qr.width() + 2 * qr.quiet_zone()) * 4;
+        assert!(png.len() < width * width / 8 / 4);
+    }
+
+    // the type and data of every chunk after the signature
+    fn chunks(png: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut res = vec![];
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            res.push((&rest[4..8], &rest[8..8 + len]));
+            rest = &rest[12 + len..];
+        }
+        res
+    }
+
+    #[test]
+    fn test_png_colours() {
+        let data = vec![vec![true, false]];
+        let png = make_png(&data).unwrap();
+        let kinds: Vec<&[u8]> = chunks(&png).iter().map(|c| c.0).collect();
+        assert_eq!(kinds, [b"IHDR", b"pHYs", b"IDAT", b"IEND"]);
+
+        let options = RenderOptions {
+            foreground: [0x12, 0x34, 0x56, 255],
+            background: [255, 255, 255, 0],
+            dpi: 600,
+            ..Default::default()
+        };
+        let png = make_png_with(&data, &options).unwrap();
+        let chunks = chunks(&png);
+        assert_eq!(chunks[0].1[9], 3);
+        assert_eq!(
+            chunks[1],
+            (&b"pHYs"[..], &[0, 0, 0x5C, 0x46, 0, 0, 0x5C, 0x46, 1][..])
+        );
+        assert_eq!(
+            chunks[2],
+            (&b"PLTE"[..], &[255, 255, 255, 0x12, 0x34, 0x56][..])
+        );
+        assert_eq!(chunks[3], (&b"tRNS"[..], &[0, 255][..]));
+        assert_eq!(chunks[4].0, b"IDAT");
+    }
+}
@@ -0,0 +1,281 @@
+use crate::layout::Qr;
+use crate::style::{Style, shapes};
+
+const MM_PER_INCH: f64 = 25.4;
+
+/// How to draw a raster image of a qr code, used by every image format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// width of a module in pixels
+    pub scale: usize,
+    /// width of the blank border in modules, the qr's own if `None`
+    pub quiet_zone: Option<usize>,
+    /// rgba of dark modules
+    pub foreground: [u8; 4],
+    /// rgba of light modules and the quiet zone
+    pub background: [u8; 4],
+    /// resolution written to the file, it doesn't change the pixels
+    pub dpi: u32,
+    /// shapes of the modules and finder patterns
+    pub style: Style,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            scale: 4,
+            quiet_zone: None,
+            foreground: [0, 0, 0, 255],
+            background: [255, 255, 255, 255],
+            dpi: 300,
+            style: Style::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    /// the biggest scale that keeps the image, quiet zone and all, within `mm` millimetres wide
+    /// when printed at `dpi`, but never less than one pixel per module
+    pub fn fit_size(mut self, qr: &Qr, mm: f64) -> Self {
+        let pixels = mm / MM_PER_INCH * self.dpi as f64;
+        self.scale = ((pixels / self.modules(qr) as f64) as usize).max(1);
+        self
+    }
+
+    /// `dpi` the way bmp and png files want it
+    pub fn pixels_per_meter(&self) -> u32 {
+        (self.dpi as f64 * 1000.0 / MM_PER_INCH).round() as u32
+    }
+
+    /// the image width in modules, quiet zone included
+    pub fn modules(&self, qr: &Qr) -> usize {
+        qr.width() + 2 * self.quiet_zone.unwrap_or(qr.quiet_zone())
+    }
+
+    /// the symbol scaled up with its quiet zone, true for dark pixels
+    pub fn pixels(&self, qr: &Qr) -> Vec<Vec<bool>> {
+        let quiet = self.quiet_zone.unwrap_or(qr.quiet_zone());
+        let size = self.modules(qr) * self.scale;
+        if !self.style.is_square() {
+            return self.styled_pixels(qr, quiet, size);
+        }
+        (0..size)
+            .map(|y| {
+                (0..size)
+                    .map(|x| {
+                        let (row, col) = (
+                            (y / self.scale).wrapping_sub(quiet),
+                            (x / self.scale).wrapping_sub(quiet),
+                        );
+                        qr.data
+                            .get(row)
+                            .and_then(|r| r.get(col))
+                            .is_some_and(|&m| m)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // every pixel whose centre is in an odd number of shapes is dark
+    fn styled_pixels(&self, qr: &Qr, quiet: usize, size: usize) -> Vec<Vec<bool>> {
+        let mut res = vec![vec![false; size]; size];
+        let scale = self.scale as f64;
+        for shape in shapes(qr, &self.style) {
+            let (left, top) = (
+                (shape.x + quiet as f64) * scale,
+                (shape.y + quiet as f64) * scale,
+            );
+            let (right, bottom) = (left + shape.width * scale, top + shape.height * scale);
+            let (rows, cols) = (
+                top as usize..bottom.ceil() as usize,
+                left as usize..right.ceil() as usize,
+            );
+            for (y, row) in res.iter_mut().enumerate().take(rows.end).skip(rows.start) {
+                for (x, pixel) in row.iter_mut().enumerate().take(cols.end).skip(cols.start) {
+                    let (mx, my) = ((x as f64 + 0.5) / scale, (y as f64 + 0.5) / scale);
+                    if shape.contains(mx - quiet as f64, my - quiet as f64) {
+                        *pixel = !*pixel;
+                    }
+                }
+            }
+        }
+        res
+    }
+}
+
+/// How to draw a vector image of a qr code for print
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintOptions {
+    /// width of a module in millimetres
+    pub module_mm: f64,
+    /// width of the blank border in modules, the qr's own if `None`
+    pub quiet_zone: Option<usize>,
+    /// rgb of dark modules
+    pub foreground: [u8; 3],
+    /// rgb of light modules and the quiet zone, left unpainted if `None`
+    pub background: Option<[u8; 3]>,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            module_mm: 0.5,
+            quiet_zone: None,
+            foreground: [0, 0, 0],
+            background: Some([255, 255, 255]),
+        }
+    }
+}
+
+impl PrintOptions {
+    /// modules sized so the image, quiet zone and all, is `mm` millimetres wide
+    pub fn fit_size(mut self, qr: &Qr, mm: f64) -> Self {
+        self.module_mm = mm / self.modules(qr) as f64;
+        self
+    }
+
+    /// the image width in modules, quiet zone included
+    pub fn modules(&self, qr: &Qr) -> usize {
+        qr.width() + 2 * self.quiet_zone.unwrap_or(qr.quiet_zone())
+    }
+
+    /// the image width in postscript points
+    pub fn points(&self, qr: &Qr) -> f64 {
+        self.modules(qr) as f64 * self.module_mm / MM_PER_INCH * 72.0
+    }
+}
+
+/// the runs of dark modules along each row as (x, y, length), for drawing one merged path
+/// instead of a square per module
+pub(crate) fn dark_runs(data: &[Vec<bool>]) -> Vec<(usize, usize, usize)> {
+    let mut res = vec![];
+    for (y, row) in data.iter().enumerate() {
+        let mut x = 0;
+        while x < row.len() {
+            let len = row[x..].iter().take_while(|&&dark| dark).count();
+            if len > 0 {
+                res.push((x, y, len));
+            }
+            x += len.max(1);
+        }
+    }
+    res
+}
+
+/// a number for pdf and postscript, which don't take exponents, to 4 decimal places without
+/// trailing zeros
+pub(crate) fn format_number(n: f64) -> String {
+    let res = format!("{:.4}", n);
+    res.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// the operands for a colour in pdf and postscript, each channel from 0 to 1
+pub(crate) fn format_rgb(colour: [u8; 3]) -> String {
+    colour.map(|c| format_number(c as f64 / 255.0)).join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+    use crate::style::{EyeStyle, ModuleStyle};
+
+    #[test]
+    fn test_pixels() {
+        let (qr, _) = QrBuilder::new("hello").quiet_zone(2).build().unwrap();
+        let options = RenderOptions {
+            scale: 3,
+            ..Default::default()
+        };
+        let pixels = options.pixels(&qr);
+        assert_eq!(pixels.len(), 25 * 3);
+        assert!(pixels.iter().all(|r| r.len() == 25 * 3));
+        for (y, row) in pixels.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                let (row, col) = (y / 3, x / 3);
+                let inside = (2..23).contains(&row) && (2..23).contains(&col);
+                assert_eq!(pixel, inside && qr.data[row - 2][col - 2]);
+            }
+        }
+
+        let options = RenderOptions {
+            quiet_zone: Some(0),
+            scale: 1,
+            ..Default::default()
+        };
+        assert_eq!(options.pixels(&qr), qr.data);
+    }
+
+    #[test]
+    fn test_styled_pixels() {
+        let (qr, _) = QrBuilder::new("hello").build().unwrap();
+        // square shapes come out the same as the plain squares
+        let square = RenderOptions {
+            scale: 3,
+            ..Default::default()
+        };
+        assert_eq!(square.styled_pixels(&qr, 4, 29 * 3), square.pixels(&qr));
+
+        let options = RenderOptions {
+            scale: 10,
+            style: Style {
+                modules: ModuleStyle::Circle,
+                eye_ring: EyeStyle::Circle,
+                eye_pupil: EyeStyle::Rounded,
+            },
+            ..Default::default()
+        };
+        let pixels = options.pixels(&qr);
+        // the corner of the top left finder ring is cut off, its middle and the data modules'
+        // centres stay the same
+        assert!(!pixels[40][40] && pixels[40][75]);
+        for (row, modules) in qr.data.iter().enumerate().skip(9) {
+            for (col, &m) in modules.iter().enumerate().skip(9) {
+                assert_eq!(pixels[row * 10 + 45][col * 10 + 45], m);
+            }
+        }
+        for col in 0..7 {
+            assert_eq!(pixels[3 * 10 + 45][col * 10 + 45], qr.data[3][col]);
+        }
+        // dots don't fill their whole module
+        let (row, col) = (10..21)
+            .flat_map(|r| (10..21).map(move |c| (r, c)))
+            .find(|&(r, c)| qr.data[r][c])
+            .unwrap();
+        assert!(!pixels[row * 10 + 40][col * 10 + 40]);
+    }
+
+    #[test]
+    fn test_fit_size() {
+        // 29 modules with the quiet zone, 25 mm at 600 dpi is 590 pixels
+        let (qr, _) = QrBuilder::new("hello").build().unwrap();
+        let options = RenderOptions {
+            dpi: 600,
+            ..Default::default()
+        }
+        .fit_size(&qr, 25.0);
+        assert_eq!(options.scale, 20);
+        assert_eq!(options.fit_size(&qr, 1.0).scale, 1);
+        assert_eq!(RenderOptions::default().pixels_per_meter(), 11811);
+    }
+
+    #[test]
+    fn test_dark_runs() {
+        let data = vec![
+            vec![true, true, false, true],
+            vec![false, false, false, false],
+            vec![false, true, true, true],
+        ];
+        assert_eq!(dark_runs(&data), vec![(0, 0, 2), (3, 0, 1), (1, 2, 3)]);
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(format_number(2.0), "2");
+        assert_eq!(format_number(0.5), "0.5");
+        assert_eq!(format_number(1.0 / 3.0), "0.3333");
+        assert_eq!(format_number(1e-9), "0");
+        assert_eq!(format_rgb([255, 0, 51]), "1 0 0.2");
+    }
+}
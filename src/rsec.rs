@@ -1,9 +1,14 @@
 // based on code from https://en.wikiversity.org/wiki/Reed%E2%80%93Solomon_codes_for_coders
-use crate::tables::{GF_EXP, GF_LOG};
+use crate::encoding::ECLevel;
+use crate::error::Error;
+use crate::tables::{GF_EXP, GF_LOG, VERSION_INFO};
 
 const QR_FORMAT_GENERATOR: usize = 0x537;
 const QR_FORMAT_MASK: usize = 0b101010000010010;
 
+/// the format and version codes can both correct up to 3 wrong bits
+pub const MAX_BCH_ERRORS: u32 = 3;
+
 // // turns out i didn't need this
 // pub fn gf_add(x: u8, y: u8) -> u8 {
 //     x ^ y
@@ -21,16 +26,32 @@ pub fn gf_mul(x: u8, y: u8) -> u8 {
     }
 }
 
-// // also didn't need this!
-// pub fn gf_div(x: u8, y: u8) -> u8 {
-//     if x == 0 {
-//         0
-//     } else if y == 0 {
-//         panic!("attempt to divide by zero")
-//     } else {
-//         GF_EXP[(GF_LOG[x as usize] + 255 - GF_LOG[y as usize]) % 255]
-//     }
-// }
+// panics when dividing by zero, so callers have to rule that out first
+pub(crate) fn gf_div(x: u8, y: u8) -> u8 {
+    if y == 0 {
+        panic!("attempt to divide by zero")
+    } else if x == 0 {
+        0
+    } else {
+        GF_EXP[(GF_LOG[x as usize] + 255 - GF_LOG[y as usize]) % 255]
+    }
+}
+
+// only the tests need powers
+#[cfg(test)]
+pub fn gf_pow(x: u8, power: usize) -> u8 {
+    if power == 0 {
+        1
+    } else if x == 0 {
+        0
+    } else {
+        GF_EXP[(GF_LOG[x as usize] * power) % 255]
+    }
+}
+
+pub(crate) fn gf_inverse(x: u8) -> u8 {
+    gf_div(1, x)
+}
 
 pub fn poly_mul(x: &[u8], y: &[u8]) -> Vec<u8> {
     let mut res = vec![0u8; x.len() + y.len() - 1];
@@ -44,6 +65,21 @@ pub fn poly_mul(x: &[u8], y: &[u8]) -> Vec<u8> {
     res
 }
 
+pub fn poly_scale(x: &[u8], y: u8) -> Vec<u8> {
+    x.iter().map(|&c| gf_mul(c, y)).collect()
+}
+
+/// evaluates a polynomial with the highest degree coefficient first
+pub fn poly_eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().fold(0, |acc, &c| gf_mul(acc, x) ^ c)
+}
+
+// the decoder keeps its polynomials lowest degree first, it makes the indexing in
+// berlekamp-massey and forney a lot less confusing
+fn poly_eval_low(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0, |acc, &c| gf_mul(acc, x) ^ c)
+}
+
 // this kinda sucks, maybe make a lookup table?
 pub fn rs_generator_poly(num_ec_blocks: usize) -> Vec<u8> {
     let mut res = vec![1];
@@ -54,12 +90,9 @@ pub fn rs_generator_poly(num_ec_blocks: usize) -> Vec<u8> {
     res
 }
 
-pub fn rs_encode(data: &[u8], num_ec_blocks: usize) -> Vec<u8> {
+pub fn rs_encode(data: &[u8], num_ec_blocks: usize) -> Result<Vec<u8>, Error> {
     if data.len() + num_ec_blocks > 255 {
-        panic!(
-            "message too long! was {} but max is 255!",
-            data.len() + num_ec_blocks
-        )
+        return Err(Error::BlockTooLong(data.len() + num_ec_blocks));
     }
 
     let gen_poly = rs_generator_poly(num_ec_blocks);
@@ -77,7 +110,127 @@ pub fn rs_encode(data: &[u8], num_ec_blocks: usize) -> Vec<u8> {
     }
 
     res[..data.len()].copy_from_slice(data);
-    res
+    Ok(res)
+}
+
+/// the message evaluated at each root of the generator polynomial, all zero if there's no errors
+pub fn rs_syndromes(msg: &[u8], num_ec_blocks: usize) -> Vec<u8> {
+    GF_EXP
+        .iter()
+        .take(num_ec_blocks)
+        .map(|&root| poly_eval(msg, root))
+        .collect()
+}
+
+/// corrects a block of data followed by `num_ec_blocks` ec codewords in place, `erasures` are
+/// positions in the block that are known to be wrong. needs `2 * errors + erasures` to be at
+/// most `num_ec_blocks`, returns how many codewords were changed
+pub fn rs_decode(msg: &mut [u8], num_ec_blocks: usize, erasures: &[usize]) -> Result<usize, Error> {
+    if msg.len() > 255 {
+        return Err(Error::BlockTooLong(msg.len()));
+    }
+    if let Some(&pos) = erasures.iter().find(|&&pos| pos >= msg.len()) {
+        return Err(Error::InvalidErasure(pos));
+    }
+
+    let syndromes = rs_syndromes(msg, num_ec_blocks);
+    if syndromes.iter().all(|&s| s == 0) {
+        return Ok(0);
+    }
+
+    let mut erasures = erasures.to_vec();
+    erasures.sort_unstable();
+    erasures.dedup();
+    if erasures.len() > num_ec_blocks {
+        return Err(Error::Uncorrectable);
+    }
+
+    let mut erasure_locator = vec![1];
+    for &pos in &erasures {
+        erasure_locator = poly_mul(&erasure_locator, &[1, locator_value(pos, msg.len())]);
+    }
+    let locator = rs_error_locator(&syndromes, &erasure_locator)?;
+
+    let positions: Vec<usize> = (0..msg.len())
+        .filter(|&pos| poly_eval_low(&locator, gf_inverse(locator_value(pos, msg.len()))) == 0)
+        .collect();
+    if positions.len() != locator.len() - 1 {
+        return Err(Error::Uncorrectable);
+    }
+
+    // forney algorithm
+    let mut evaluator = poly_mul(&syndromes, &locator);
+    evaluator.truncate(num_ec_blocks);
+    let derivative: Vec<u8> = locator
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, &c)| if i % 2 == 1 { c } else { 0 })
+        .collect();
+    let mut changed = 0;
+    for pos in positions {
+        let x = locator_value(pos, msg.len());
+        let x_inv = gf_inverse(x);
+        let denominator = poly_eval_low(&derivative, x_inv);
+        if denominator == 0 {
+            return Err(Error::Uncorrectable);
+        }
+        let magnitude = gf_div(gf_mul(x, poly_eval_low(&evaluator, x_inv)), denominator);
+        if magnitude != 0 {
+            msg[pos] ^= magnitude;
+            changed += 1;
+        }
+    }
+
+    if rs_syndromes(msg, num_ec_blocks).iter().any(|&s| s != 0) {
+        return Err(Error::Uncorrectable);
+    }
+    Ok(changed)
+}
+
+// the first codeword is the highest degree term
+fn locator_value(pos: usize, len: usize) -> u8 {
+    GF_EXP[len - 1 - pos]
+}
+
+// berlekamp-massey, starting from the erasure locator so it only has to find the unknown errors
+fn rs_error_locator(syndromes: &[u8], erasure_locator: &[u8]) -> Result<Vec<u8>, Error> {
+    let erasures = erasure_locator.len() - 1;
+    let mut locator = erasure_locator.to_vec();
+    let mut prev = erasure_locator.to_vec();
+    let mut len = erasures;
+
+    for r in erasures..syndromes.len() {
+        let delta = locator
+            .iter()
+            .take(r + 1)
+            .enumerate()
+            .fold(0, |acc, (i, &c)| acc ^ gf_mul(c, syndromes[r - i]));
+        prev.insert(0, 0);
+        if delta == 0 {
+            continue;
+        }
+
+        let mut next = locator.clone();
+        next.resize(next.len().max(prev.len()), 0);
+        for (i, &c) in prev.iter().enumerate() {
+            next[i] ^= gf_mul(c, delta);
+        }
+        if 2 * len <= r + erasures {
+            prev = poly_scale(&locator, gf_inverse(delta));
+            len = r + 1 + erasures - len;
+        }
+        locator = next;
+    }
+
+    while locator.len() > 1 && locator[locator.len() - 1] == 0 {
+        locator.pop();
+    }
+    let errors = len - erasures;
+    if locator.len() - 1 != len || 2 * errors + erasures > syndromes.len() {
+        return Err(Error::Uncorrectable);
+    }
+    Ok(locator)
 }
 
 pub fn qr_format_check(fmt: usize) -> usize {
@@ -90,15 +243,44 @@ pub fn qr_format_check(fmt: usize) -> usize {
     res
 }
 
-pub fn qr_format_encode(fmt: usize) -> usize {
+/// the 15 bit format information for 5 bits of ec level and mask
+pub fn qr_format_encode(fmt: usize) -> Result<usize, Error> {
     if fmt > 0b11111 {
-        panic!("tried to encode invalid format!")
+        return Err(Error::InvalidFormat(fmt));
     }
-    (fmt << 10) | qr_format_check(fmt << 10)
+    Ok((fmt << 10) | qr_format_check(fmt << 10))
 }
 
-pub fn qr_format_encode_masked(fmt: usize) -> usize {
-    qr_format_encode(fmt) ^ QR_FORMAT_MASK
+pub fn qr_format_encode_masked(fmt: usize) -> Result<usize, Error> {
+    Ok(qr_format_encode(fmt)? ^ QR_FORMAT_MASK)
+}
+
+/// the closest valid format information to a masked 15 bit word, as the ec level, mask and how
+/// many bits are different
+pub fn qr_format_decode_masked(word: usize) -> (ECLevel, u8, u32) {
+    let (fmt, distance) = (0..32)
+        .filter_map(|fmt| {
+            Some((
+                fmt,
+                (qr_format_encode_masked(fmt).ok()? ^ word).count_ones(),
+            ))
+        })
+        .min_by_key(|(_, distance)| *distance)
+        .unwrap();
+    (
+        ECLevel::from_format_bits(fmt >> 3),
+        (fmt & 0b111) as u8,
+        distance,
+    )
+}
+
+/// the closest valid version information to an 18 bit word, as the version and how many bits are
+/// different
+pub fn qr_version_decode(word: usize) -> (usize, u32) {
+    (7..=40)
+        .map(|version| (version, (VERSION_INFO[version - 1] ^ word).count_ones()))
+        .min_by_key(|(_, distance)| *distance)
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -119,17 +301,35 @@ mod tests {
         assert_eq!(gf_mul(0b10001001, 0b00101010), 0b11000011);
     }
 
-    // #[test]
-    // fn test_gf_div() {
-    //     assert_eq!(gf_div(0, 0b00101010), 0);
-    //     assert_eq!(gf_div(0b10001001, 0b00101010), 0b11011100);
-    // }
+    #[test]
+    fn test_gf_div() {
+        assert_eq!(gf_div(0, 0b00101010), 0);
+        assert_eq!(gf_div(0b10001001, 0b00101010), 0b11011100);
+        assert_eq!(gf_div(gf_mul(0x53, 0xCA), 0xCA), 0x53);
+    }
 
-    // #[test]
-    // #[should_panic]
-    // fn test_gf_div_zero() {
-    //     let _ = gf_div(0b10001001, 0);
-    // }
+    #[test]
+    #[should_panic]
+    fn test_gf_div_zero() {
+        let _ = gf_div(0b10001001, 0);
+    }
+
+    #[test]
+    fn test_gf_pow_inverse() {
+        assert_eq!(gf_pow(2, 8), 0x1D);
+        assert_eq!(gf_pow(0, 0), 1);
+        assert_eq!(gf_pow(0x53, 255), 1);
+        for x in 1..=255 {
+            assert_eq!(gf_mul(x, gf_inverse(x)), 1);
+        }
+    }
+
+    #[test]
+    fn test_poly_eval() {
+        // x^2 + 2x + 3 at 2 is 4 ^ 4 ^ 3
+        assert_eq!(poly_eval(&[1, 2, 3], 2), 3);
+        assert_eq!(poly_eval_low(&[3, 2, 1], 2), 3);
+    }
 
     #[test]
     fn test_poly_mul() {
@@ -161,7 +361,7 @@ mod tests {
             0x40, 0xD2, 0x75, 0x47, 0x76, 0x17, 0x32, 0x06, 0x27, 0x26, 0x96, 0xC6, 0xC6, 0x96,
             0x70, 0xEC,
         ];
-        let res = rs_encode(&data, 10);
+        let res = rs_encode(&data, 10).unwrap();
         assert_eq!(
             res,
             vec![
@@ -177,7 +377,7 @@ mod tests {
             0x40, 0x77, 0x46, 0x57, 0x37, 0x42, 0x03, 0xA3, 0x30, 0xEC, 0x11, 0xEC, 0x11, 0xEC,
             0x11, 0xEC, 0x11, 0xEC, 0x11,
         ];
-        let res = rs_encode(&data, 7);
+        let res = rs_encode(&data, 7).unwrap();
         assert_eq!(
             res,
             vec![
@@ -187,8 +387,116 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_encode_too_long() {
+        assert_eq!(rs_encode(&[0; 250], 10), Err(Error::BlockTooLong(260)));
+    }
+
+    // xorshift so the tests don't need a rng
+    fn corrupt(msg: &mut [u8], count: usize, seed: u32) -> Vec<usize> {
+        let mut state = seed;
+        let mut positions = vec![];
+        while positions.len() < count {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let pos = state as usize % msg.len();
+            if !positions.contains(&pos) {
+                msg[pos] ^= (state >> 8) as u8 | 1;
+                positions.push(pos);
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn test_decode() {
+        let data: Vec<u8> = (0..40u32).map(|i| (i * 7 + 3) as u8).collect();
+        let encoded = rs_encode(&data, 18).unwrap();
+
+        let mut msg = encoded.clone();
+        assert_eq!(rs_decode(&mut msg, 18, &[]), Ok(0));
+
+        for errors in 1..=9 {
+            let mut msg = encoded.clone();
+            corrupt(&mut msg, errors, errors as u32 * 31);
+            assert_eq!(rs_decode(&mut msg, 18, &[]), Ok(errors));
+            assert_eq!(msg, encoded);
+        }
+    }
+
+    #[test]
+    fn test_decode_erasures() {
+        let data: Vec<u8> = (0..40u32).map(|i| (i * 11 + 5) as u8).collect();
+        let encoded = rs_encode(&data, 18).unwrap();
+
+        // all erasures
+        let mut msg = encoded.clone();
+        let erasures = corrupt(&mut msg, 18, 1234);
+        assert_eq!(rs_decode(&mut msg, 18, &erasures), Ok(18));
+        assert_eq!(msg, encoded);
+
+        // a mix, with an erasure that was actually right
+        for (errors, erasures) in [(1, 15), (4, 9), (8, 1)] {
+            let mut msg = encoded.clone();
+            let mut known = corrupt(&mut msg, errors + erasures, 99 + errors as u32);
+            known.truncate(erasures);
+            let correct = (0..msg.len()).find(|i| msg[*i] == encoded[*i]).unwrap();
+            known.push(correct);
+            assert_eq!(rs_decode(&mut msg, 18, &known), Ok(errors + erasures));
+            assert_eq!(msg, encoded);
+        }
+    }
+
+    #[test]
+    fn test_decode_uncorrectable() {
+        let data: Vec<u8> = (0..40u32).map(|i| (i * 13 + 1) as u8).collect();
+        let encoded = rs_encode(&data, 18).unwrap();
+
+        let mut msg = encoded.clone();
+        corrupt(&mut msg, 10, 42);
+        assert_eq!(rs_decode(&mut msg, 18, &[]), Err(Error::Uncorrectable));
+
+        let mut msg = encoded.clone();
+        let erasures = corrupt(&mut msg, 19, 7);
+        assert_eq!(
+            rs_decode(&mut msg, 18, &erasures),
+            Err(Error::Uncorrectable)
+        );
+
+        assert_eq!(
+            rs_decode(&mut msg, 18, &[58]),
+            Err(Error::InvalidErasure(58))
+        );
+    }
+
     #[test]
     fn test_format_encode() {
-        assert_eq!(qr_format_encode(0b00011), 0b000111101011001)
+        assert_eq!(qr_format_encode(0b00011), Ok(0b000111101011001));
+        assert_eq!(
+            qr_format_encode(0b100000),
+            Err(Error::InvalidFormat(0b100000))
+        );
+    }
+
+    #[test]
+    fn test_format_decode() {
+        for ec in ECLevel::ASCENDING {
+            for mask in 0..8 {
+                let word = qr_format_encode_masked((ec as usize) << 3 | mask as usize).unwrap();
+                assert_eq!(qr_format_decode_masked(word), (ec, mask, 0));
+                let damaged = word ^ 0b100000000000101;
+                assert_eq!(qr_format_decode_masked(damaged), (ec, mask, 3));
+            }
+        }
+    }
+
+    #[test]
+    fn test_version_decode() {
+        for version in 7..=40 {
+            let word = VERSION_INFO[version - 1];
+            assert_eq!(qr_version_decode(word), (version, 0));
+            assert_eq!(qr_version_decode(word ^ 0b100000000000010001), (version, 3));
+        }
     }
 }
@@ -0,0 +1,803 @@
+use crate::error::Error;
+use crate::layout::{self, ModuleType};
+
+/// pixels darker than this count as dark modules
+const THRESHOLD: u8 = 128;
+
+/// dark, light, dark, light, dark through the middle of a finder pattern, the same shape the
+/// `FINDER_LIKE` penalty looks for
+const FINDER_RATIO: [f64; 5] = [1.0, 1.0, 3.0, 1.0, 1.0];
+
+/// the adaptive threshold works on blocks of this many pixels square
+const BLOCK_SIZE: usize = 8;
+
+/// blocks with less contrast than this are assumed to be all one colour
+const MIN_CONTRAST: u8 = 24;
+
+/// Modules sampled out of an image
+#[derive(Debug, PartialEq, Clone)]
+pub struct Grid {
+    pub modules: Vec<Vec<bool>>,
+    /// width of a module in pixels
+    pub module_size: f64,
+    /// narrowest blank border around the symbol, in modules
+    pub quiet_zone: usize,
+}
+
+/// samples the module centres of a clean, axis aligned symbol, like the ones `qr_to_bitmap`
+/// makes. the symbol is taken to be the bounding box of the dark pixels and the module size comes
+/// from the top left finder pattern
+pub fn sample_grid(image: &[Vec<u8>]) -> Result<Grid, Error> {
+    let dark = |x: usize, y: usize| image[y][x] < THRESHOLD;
+    let (img_width, img_height) = (image.first().map_or(0, |r| r.len()), image.len());
+
+    let dark_rows: Vec<usize> = (0..img_height)
+        .filter(|&y| (0..img_width).any(|x| dark(x, y)))
+        .collect();
+    let (Some(&top), Some(&bottom)) = (dark_rows.first(), dark_rows.last()) else {
+        return Err(Error::NoCodeFound);
+    };
+    let left = (0..img_width)
+        .find(|&x| dark_rows.iter().any(|&y| dark(x, y)))
+        .unwrap();
+    let right = (0..img_width)
+        .rfind(|&x| dark_rows.iter().any(|&y| dark(x, y)))
+        .unwrap();
+
+    // the top left finder pattern is 7 modules wide
+    let finder = (left..=right).take_while(|&x| dark(x, top)).count();
+    // the corner of the bounding box isn't dark, so it's not a clean symbol
+    if finder == 0 {
+        return Err(Error::NoCodeFound);
+    }
+    let module_size = finder as f64 / 7.0;
+    let (px_width, px_height) = ((right - left + 1) as f64, (bottom - top + 1) as f64);
+
+    let width = snap_width((px_width / module_size).round() as usize)?;
+    if (px_height / px_width - 1.0).abs() > 0.1 {
+        return Err(Error::NoCodeFound);
+    }
+
+    let modules = (0..width)
+        .map(|row| {
+            let y = top + ((row as f64 + 0.5) * px_height / width as f64) as usize;
+            (0..width)
+                .map(|col| {
+                    dark(
+                        left + ((col as f64 + 0.5) * px_width / width as f64) as usize,
+                        y,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    let margin = left
+        .min(top)
+        .min(img_width - 1 - right)
+        .min(img_height - 1 - bottom);
+    Ok(Grid {
+        modules,
+        module_size,
+        quiet_zone: (margin as f64 / module_size) as usize,
+    })
+}
+
+/// greyscale pixels in one buffer, `width` per row, split into rows
+pub fn image_from_luma(buf: &[u8], width: usize) -> Result<Vec<Vec<u8>>, Error> {
+    if width == 0 || buf.is_empty() {
+        return Err(Error::EmptyImage);
+    }
+    if !buf.len().is_multiple_of(width) {
+        return Err(Error::InvalidImage);
+    }
+    Ok(buf.chunks(width).map(|row| row.to_vec()).collect())
+}
+
+/// finds a symbol in a photo or scan. the image is thresholded against the area around each
+/// pixel, the three finder patterns give the corners and the bottom right alignment pattern, when
+/// there is one, corrects for perspective before the module centres are sampled
+pub fn detect(image: &[Vec<u8>]) -> Result<Grid, Error> {
+    let dark = threshold(image);
+    let [top_left, top_right, bottom_left] = find_finders(&dark)?;
+    // runs along the image axes are too long when the symbol is rotated, so measure across the
+    // finder patterns towards each other when possible. with perspective the modules near each
+    // end of a side are different sizes, so each side is counted with its own ends
+    let modules_between = |a: &Finder, b: &Finder| {
+        let module = match (
+            module_towards(&dark, a.pos, b.pos),
+            module_towards(&dark, b.pos, a.pos),
+        ) {
+            (Some(x), Some(y)) => (x + y) / 2.0,
+            _ => (a.module + b.module) / 2.0,
+        };
+        distance(a.pos, b.pos) / module
+    };
+    let between =
+        (modules_between(&top_left, &top_right) + modules_between(&top_left, &bottom_left)) / 2.0;
+    let estimate = snap_width((between.round() as usize).saturating_add(7))?;
+
+    let span =
+        (distance(top_left.pos, top_right.pos) + distance(top_left.pos, bottom_left.pos)) / 2.0;
+    let axis_module = (top_left.module + top_right.module + bottom_left.module) / 3.0;
+    let corners = [top_left.pos, top_right.pos, bottom_left.pos];
+    let attempt = |width: usize| {
+        sample_perspective(
+            &dark,
+            corners,
+            width,
+            span / (width - 7) as f64,
+            axis_module,
+        )
+    };
+    // strong perspective throws the estimate off, so the widths either side get a go too
+    let mut best: Option<(Grid, f64)> = None;
+    for width in [estimate, estimate - 4, estimate + 4] {
+        let Ok(found) = attempt(width) else {
+            continue;
+        };
+        if best.as_ref().is_none_or(|(_, wrong)| found.1 < *wrong) {
+            best = Some(found);
+        }
+    }
+    let (grid, _) = best.ok_or(Error::NoCodeFound)?;
+    // big symbols say their version, which beats any guess
+    match layout::read_version(&grid.modules) {
+        Ok((version, _)) if layout::version_to_width(version)? != grid.modules.len() => {
+            Ok(attempt(layout::version_to_width(version)?)?.0)
+        }
+        _ => Ok(grid),
+    }
+}
+
+/// true for dark pixels. each block of pixels is compared to the average of the blocks around it
+/// so uneven lighting doesn't matter
+pub fn threshold(image: &[Vec<u8>]) -> Vec<Vec<bool>> {
+    let (width, height) = (image.first().map_or(0, |r| r.len()), image.len());
+    let (blocks_x, blocks_y) = (width.div_ceil(BLOCK_SIZE), height.div_ceil(BLOCK_SIZE));
+
+    let mut averages = vec![vec![0u32; blocks_x]; blocks_y];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let pixels = image[by * BLOCK_SIZE..((by + 1) * BLOCK_SIZE).min(height)]
+                .iter()
+                .flat_map(|row| &row[bx * BLOCK_SIZE..((bx + 1) * BLOCK_SIZE).min(width)]);
+            let (mut min, mut max, mut sum, mut count) = (u8::MAX, 0, 0, 0);
+            for &p in pixels {
+                min = min.min(p);
+                max = max.max(p);
+                sum += p as u32;
+                count += 1;
+            }
+            averages[by][bx] = if max - min > MIN_CONTRAST {
+                sum / count
+            } else {
+                // flat block, probably background unless the blocks around it are darker
+                let mut avg = min as u32 / 2;
+                if by > 0 && bx > 0 {
+                    let neighbours = (averages[by - 1][bx]
+                        + 2 * averages[by][bx - 1]
+                        + averages[by - 1][bx - 1])
+                        / 4;
+                    if (min as u32) < neighbours {
+                        avg = neighbours;
+                    }
+                }
+                avg
+            };
+        }
+    }
+
+    let mut res = vec![vec![false; width]; height];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let rows = by.saturating_sub(2)..(by + 3).min(blocks_y);
+            let cols = bx.saturating_sub(2)..(bx + 3).min(blocks_x);
+            let count = (rows.len() * cols.len()) as u32;
+            let sum: u32 = averages[rows]
+                .iter()
+                .map(|r| r[cols.clone()].iter().sum::<u32>())
+                .sum();
+            let limit = sum / count;
+            for y in by * BLOCK_SIZE..((by + 1) * BLOCK_SIZE).min(height) {
+                for x in bx * BLOCK_SIZE..((bx + 1) * BLOCK_SIZE).min(width) {
+                    res[y][x] = (image[y][x] as u32) <= limit;
+                }
+            }
+        }
+    }
+    res
+}
+
+// from the centre of a finder pattern out through its edge in both directions along the line to
+// `to` is 7 modules
+fn module_towards(dark: &[Vec<bool>], from: (f64, f64), to: (f64, f64)) -> Option<f64> {
+    let (width, height) = (dark.first().map_or(0, |r| r.len()), dark.len());
+    let len = distance(from, to);
+    let step = ((to.0 - from.0) / len / 2.0, (to.1 - from.1) / len / 2.0);
+    let mut total = 0;
+    for dir in [1.0, -1.0] {
+        // dark centre, light ring, dark ring, then out
+        let (mut colour, mut changes, mut steps) = (true, 0, 0);
+        while changes < 3 {
+            let (x, y) = (
+                from.0 + step.0 * steps as f64 * dir,
+                from.1 + step.1 * steps as f64 * dir,
+            );
+            if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 || steps as f64 > len {
+                return None;
+            }
+            if dark[y as usize][x as usize] != colour {
+                colour = !colour;
+                changes += 1;
+            }
+            steps += 1;
+        }
+        total += steps - 1;
+    }
+    // half pixel steps
+    Some(total as f64 / 2.0 / 7.0)
+}
+
+// symbols are 17 + 4 * version modules wide. a bad module size can make `width` anything at all,
+// which just fails to be a version
+fn snap_width(width: usize) -> Result<usize, Error> {
+    let width = (width.saturating_sub(17).saturating_add(2) / 4 * 4).saturating_add(17);
+    layout::width_to_version(width).map_err(|_| Error::NoCodeFound)?;
+    Ok(width)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Finder {
+    pos: (f64, f64),
+    module: f64,
+    count: usize,
+}
+
+// module size if the runs look like a finder pattern
+fn finder_ratio(runs: &[usize; 5]) -> Option<f64> {
+    let module = runs.iter().sum::<usize>() as f64 / 7.0;
+    let ok = runs
+        .iter()
+        .zip(FINDER_RATIO)
+        .all(|(&run, ratio)| run > 0 && (run as f64 - ratio * module).abs() < ratio * module / 2.0);
+    ok.then_some(module)
+}
+
+// walks out from a dark pixel in both directions along a line, returning the centre and module
+// size if it crosses a finder pattern
+fn cross_check_finder(
+    len: usize,
+    center: usize,
+    dark: impl Fn(usize) -> bool,
+) -> Option<(f64, f64)> {
+    if !dark(center) {
+        return None;
+    }
+    let mut runs = [0; 5];
+    let mut i = center;
+    for (run, colour) in [(2, true), (1, false), (0, true)] {
+        while dark(i) == colour {
+            runs[run] += 1;
+            if i == 0 {
+                break;
+            }
+            i -= 1;
+        }
+    }
+    let start = center + 1 - runs[2];
+    let mut i = center + 1;
+    for (run, colour) in [(2, true), (3, false), (4, true)] {
+        while i < len && dark(i) == colour {
+            runs[run] += 1;
+            i += 1;
+        }
+    }
+    let module = finder_ratio(&runs)?;
+    Some((start as f64 + runs[2] as f64 / 2.0, module))
+}
+
+fn find_finders(dark: &[Vec<bool>]) -> Result<[Finder; 3], Error> {
+    let (width, height) = (dark.first().map_or(0, |r| r.len()), dark.len());
+    let mut candidates: Vec<Finder> = vec![];
+
+    for y in 0..height {
+        // run lengths along the row, starting with a dark one
+        let mut runs: Vec<(usize, usize)> = vec![];
+        let mut x = 0;
+        while x < width {
+            let start = x;
+            while x < width && dark[y][x] == dark[y][start] {
+                x += 1;
+            }
+            if dark[y][start] || !runs.is_empty() {
+                runs.push((start, x - start));
+            }
+        }
+
+        for window in runs.windows(5).step_by(2) {
+            let lens = [
+                window[0].1,
+                window[1].1,
+                window[2].1,
+                window[3].1,
+                window[4].1,
+            ];
+            if finder_ratio(&lens).is_none() {
+                continue;
+            }
+            let cx = window[2].0 + window[2].1 / 2;
+            let Some((cy, v_module)) = cross_check_finder(height, y, |i| dark[i][cx]) else {
+                continue;
+            };
+            let row = cy as usize;
+            let Some((cx, h_module)) = cross_check_finder(width, cx, |i| dark[row][i]) else {
+                continue;
+            };
+            let found = Finder {
+                pos: (cx, cy),
+                module: (v_module + h_module) / 2.0,
+                count: 1,
+            };
+            match candidates.iter_mut().find(|c| {
+                distance(c.pos, found.pos) < c.module * 2.0
+                    && (c.module - found.module).abs() < c.module / 2.0
+            }) {
+                Some(c) => {
+                    let n = c.count as f64;
+                    c.pos = (
+                        (c.pos.0 * n + found.pos.0) / (n + 1.0),
+                        (c.pos.1 * n + found.pos.1) / (n + 1.0),
+                    );
+                    c.module = (c.module * n + found.module) / (n + 1.0);
+                    c.count += 1;
+                }
+                None => candidates.push(found),
+            }
+        }
+    }
+
+    // the three that look most like the corners of a square
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.count));
+    candidates.truncate(10);
+    let mut best: Option<(f64, [Finder; 3])> = None;
+    for (i, a) in candidates.iter().enumerate() {
+        for (j, b) in candidates.iter().enumerate().skip(i + 1) {
+            for c in candidates.iter().skip(j + 1) {
+                let Some(corners) = order_finders([*a, *b, *c]) else {
+                    continue;
+                };
+                let [tl, tr, bl] = corners;
+                let (top, left) = (distance(tl.pos, tr.pos), distance(tl.pos, bl.pos));
+                let diagonal = distance(tr.pos, bl.pos);
+                let modules = [tl.module, tr.module, bl.module];
+                let max = modules.iter().cloned().fold(0.0, f64::max);
+                let min = modules.iter().cloned().fold(f64::MAX, f64::min);
+                let shape = (top - left).abs() / (top + left)
+                    + (diagonal - top.hypot(left)).abs() / diagonal;
+                if top < tl.module * 10.0 || shape > 0.3 || (max - min) / max > 0.5 {
+                    continue;
+                }
+                // real finder patterns get crossed by about three modules worth of rows, a lot
+                // more than things in the data that happen to look like them
+                let thin = [tl, tr, bl]
+                    .iter()
+                    .map(|f| f.module / f.count as f64)
+                    .fold(0.0, f64::max);
+                let error = shape + (max - min) / max / 4.0 + thin / 2.0;
+                if best.is_none_or(|(e, _)| error < e) {
+                    best = Some((error, corners));
+                }
+            }
+        }
+    }
+    best.map(|(_, corners)| corners).ok_or(Error::NoCodeFound)
+}
+
+// top left, top right, bottom left. the top left one is opposite the longest side
+fn order_finders(finders: [Finder; 3]) -> Option<[Finder; 3]> {
+    let [a, b, c] = finders;
+    let (ab, bc, ac) = (
+        distance(a.pos, b.pos),
+        distance(b.pos, c.pos),
+        distance(a.pos, c.pos),
+    );
+    let [tl, mut tr, mut bl] = if bc >= ab && bc >= ac {
+        [a, b, c]
+    } else if ac >= ab {
+        [b, a, c]
+    } else {
+        [c, a, b]
+    };
+    // y goes down so clockwise from top left to top right to bottom left is a positive turn
+    let cross = (tr.pos.0 - tl.pos.0) * (bl.pos.1 - tl.pos.1)
+        - (tr.pos.1 - tl.pos.1) * (bl.pos.0 - tl.pos.0);
+    if cross == 0.0 {
+        return None;
+    }
+    if cross < 0.0 {
+        std::mem::swap(&mut tr, &mut bl);
+    }
+    Some([tl, tr, bl])
+}
+
+// centre of a dark run about a module long with a light run of about a module and then something
+// dark on each side, like through the middle of an alignment pattern
+fn cross_check_alignment(
+    len: usize,
+    center: usize,
+    module: f64,
+    dark: impl Fn(usize) -> bool,
+) -> Option<f64> {
+    if !dark(center) {
+        return None;
+    }
+    // light before, centre, light after
+    let mut runs = [0; 3];
+    let mut i = center;
+    while i > 0 && dark(i - 1) {
+        i -= 1;
+    }
+    let start = i;
+    while i > 0 && !dark(i - 1) {
+        runs[0] += 1;
+        i -= 1;
+    }
+    let mut j = center;
+    while j + 1 < len && dark(j + 1) {
+        j += 1;
+    }
+    runs[1] = j + 1 - start;
+    while j + 1 < len && !dark(j + 1) {
+        runs[2] += 1;
+        j += 1;
+    }
+    let near = |run: usize| (run as f64 - module).abs() < module / 2.0 + 0.5;
+    (i > 0 && j + 1 < len && runs.iter().all(|&run| near(run)))
+        .then_some(start as f64 + runs[1] as f64 / 2.0)
+}
+
+// places that look like the dark centre of an alignment pattern near `estimate`, closest first.
+// looks further away if there's nothing close
+fn find_alignments(dark: &[Vec<bool>], estimate: (f64, f64), module: f64) -> Vec<(f64, f64)> {
+    let (width, height) = (dark.first().map_or(0, |r| r.len()), dark.len());
+    for allowance in [4.0, 8.0, 16.0] {
+        let reach = allowance * module;
+        let rows =
+            (estimate.1 - reach).max(0.0) as usize..((estimate.1 + reach) as usize).min(height);
+        let cols =
+            (estimate.0 - reach).max(0.0) as usize..((estimate.0 + reach) as usize).min(width);
+        let mut res: Vec<(f64, f64)> = vec![];
+        for y in rows {
+            for x in cols.clone() {
+                let Some(cx) = cross_check_alignment(width, x, module, |i| dark[y][i]) else {
+                    continue;
+                };
+                let col = cx as usize;
+                let Some(cy) = cross_check_alignment(height, y, module, |i| dark[i][col]) else {
+                    continue;
+                };
+                if res.iter().all(|&found| distance(found, (cx, cy)) > module) {
+                    res.push((cx, cy));
+                }
+            }
+        }
+        if !res.is_empty() {
+            res.sort_by(|a, b| distance(*a, estimate).total_cmp(&distance(*b, estimate)));
+            return res;
+        }
+    }
+    vec![]
+}
+
+// projective transform from module coordinates to pixels, solved from four points
+fn perspective(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Option<[f64; 8]> {
+    let mut m = [[0.0; 9]; 8];
+    for (i, ((u, v), (x, y))) in from.into_iter().zip(to).enumerate() {
+        m[2 * i] = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x, x];
+        m[2 * i + 1] = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y, y];
+    }
+    // gaussian elimination with partial pivoting
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let pivot_row = m[col];
+        for (i, row) in m.iter_mut().enumerate() {
+            if i != col {
+                let factor = row[col] / pivot_row[col];
+                for (x, p) in row.iter_mut().zip(pivot_row).skip(col) {
+                    *x -= factor * p;
+                }
+            }
+        }
+    }
+    Some(std::array::from_fn(|i| m[i][8] / m[i][i]))
+}
+
+fn transform(h: &[f64; 8], (u, v): (f64, f64)) -> (f64, f64) {
+    let w = h[6] * u + h[7] * v + 1.0;
+    (
+        (h[0] * u + h[1] * v + h[2]) / w,
+        (h[3] * u + h[4] * v + h[5]) / w,
+    )
+}
+
+// the grid along with the fraction of finder, alignment and timing modules that came out wrong
+fn sample_perspective(
+    dark: &[Vec<bool>],
+    [tl, tr, bl]: [(f64, f64); 3],
+    width: usize,
+    module_size: f64,
+    axis_module: f64,
+) -> Result<(Grid, f64), Error> {
+    let (img_width, img_height) = (dark.first().map_or(0, |r| r.len()), dark.len());
+    let w = width as f64;
+    let version = layout::width_to_version(width)?;
+
+    let fixed = layout::make_fixed_patterns(version)?;
+    let is_fixed: Vec<Vec<bool>> = (0..width)
+        .map(|row| {
+            (0..width)
+                .map(|col| {
+                    matches!(
+                        layout::module_type(version, (row, col)),
+                        Ok(ModuleType::Finder | ModuleType::Alignment | ModuleType::Timing)
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    // how many finder, alignment and timing modules a transform gets wrong
+    let score = |h: [f64; 8]| {
+        let modules = sample(dark, &h, width)?;
+        let errors = (0..width)
+            .flat_map(|row| (0..width).map(move |col| (row, col)))
+            .filter(|&(row, col)| is_fixed[row][col] && modules[row][col] != fixed[row][col])
+            .count();
+        Some((errors, h, modules))
+    };
+
+    // the bottom right corner is a guess without perspective. the alignment patterns along the
+    // diagonal fix that, each one is easier to find with the transform from the one before. it's
+    // easy to find the wrong one on big symbols so a few are tried at each step, and whichever
+    // gets the fixed patterns most right wins
+    let finders = [(3.5, 3.5), (w - 3.5, 3.5), (3.5, w - 3.5)];
+    let fit = |from: (f64, f64), to: (f64, f64)| {
+        perspective([finders[0], finders[1], finders[2], from], [tl, tr, bl, to])
+    };
+    let affine = (tr.0 + bl.0 - tl.0, tr.1 + bl.1 - tl.1);
+    let mut best = fit((w - 3.5, w - 3.5), affine).and_then(score);
+    // ones near the middle are too close to the line between the top right and bottom left
+    // finders to say anything about perspective
+    let diagonal = layout::get_alignment_locations(version)?
+        .into_iter()
+        .filter_map(|(row, col)| (row == col && row as f64 > w * 0.6).then_some(row));
+    for pos in diagonal {
+        let Some((_, h, _)) = &best else {
+            break;
+        };
+        let at = (pos as f64 + 0.5, pos as f64 + 0.5);
+        for to in find_alignments(dark, transform(h, at), axis_module)
+            .into_iter()
+            .take(5)
+        {
+            let Some(candidate) = fit(at, to).and_then(score) else {
+                continue;
+            };
+            if best.as_ref().is_none_or(|(e, _, _)| candidate.0 < *e) {
+                best = Some(candidate);
+            }
+        }
+    }
+    let (errors, h, modules) = best.ok_or(Error::NoCodeFound)?;
+
+    let margin = [(0.0, 0.0), (w, 0.0), (0.0, w), (w, w)]
+        .into_iter()
+        .map(|corner| {
+            let (x, y) = transform(&h, corner);
+            x.min(y)
+                .min(img_width as f64 - x)
+                .min(img_height as f64 - y)
+        })
+        .fold(f64::MAX, f64::min);
+    let grid = Grid {
+        modules,
+        module_size,
+        quiet_zone: (margin.max(0.0) / module_size) as usize,
+    };
+    let total = is_fixed.iter().flatten().filter(|&&fixed| fixed).count();
+    Ok((grid, errors as f64 / total as f64))
+}
+
+// the module centres, None if any are outside the image
+fn sample(dark: &[Vec<bool>], h: &[f64; 8], width: usize) -> Option<Vec<Vec<bool>>> {
+    let (img_width, img_height) = (dark.first().map_or(0, |r| r.len()), dark.len());
+    (0..width)
+        .map(|row| {
+            (0..width)
+                .map(|col| {
+                    let (x, y) = transform(h, (col as f64 + 0.5, row as f64 + 0.5));
+                    let inside =
+                        x >= 0.0 && y >= 0.0 && x < img_width as f64 && y < img_height as f64;
+                    inside.then(|| dark[y as usize][x as usize])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitmap::{qr_to_bitmap, read_bitmap};
+    use crate::builder::QrBuilder;
+    use crate::encoding::ECLevel;
+
+    fn render(modules: &[Vec<bool>], scale: usize, quiet: usize) -> Vec<Vec<u8>> {
+        let size = (modules.len() + 2 * quiet) * scale;
+        (0..size)
+            .map(|y| {
+                (0..size)
+                    .map(|x| {
+                        let (row, col) = (
+                            (y / scale).wrapping_sub(quiet),
+                            (x / scale).wrapping_sub(quiet),
+                        );
+                        match modules.get(row).and_then(|r| r.get(col)) {
+                            Some(true) => 0,
+                            _ => 255,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sample_bitmap() {
+        for (text, quiet) in [("hello", 4), ("HELLO WORLD 1234567890", 0), ("x", 2)] {
+            let (qr, _) = QrBuilder::new(text).quiet_zone(quiet).build().unwrap();
+            let image = read_bitmap(&qr_to_bitmap(&qr).unwrap()).unwrap();
+            let grid = sample_grid(&image).unwrap();
+            assert_eq!(grid.modules, qr.data);
+            assert_eq!(grid.module_size, 4.0);
+            assert_eq!(grid.quiet_zone, quiet);
+        }
+    }
+
+    #[test]
+    fn test_sample_scales() {
+        let (qr, _) = QrBuilder::new(&"a".repeat(200)).build().unwrap();
+        for scale in [1, 3, 7] {
+            let grid = sample_grid(&render(&qr.data, scale, 1)).unwrap();
+            assert_eq!(grid.modules, qr.data);
+            assert_eq!(grid.quiet_zone, 1);
+        }
+    }
+
+    #[test]
+    fn test_sample_nothing() {
+        assert_eq!(
+            sample_grid(&[vec![255; 30], vec![255; 30]]),
+            Err(Error::NoCodeFound)
+        );
+        assert_eq!(sample_grid(&[]), Err(Error::NoCodeFound));
+
+        // the top left dark pixel isn't in the top row, so there's no finder to size modules by
+        let mut image = vec![vec![255; 30]; 30];
+        image[2][20] = 0;
+        image[10][3] = 0;
+        assert_eq!(sample_grid(&image), Err(Error::NoCodeFound));
+    }
+
+    // a photo of the symbol, warped so its corners land on `corners` with the lighting getting
+    // brighter to the right and some noise
+    fn photograph(modules: &[Vec<bool>], corners: [(f64, f64); 4], size: usize) -> Vec<Vec<u8>> {
+        let source = render(modules, 5, 4);
+        let s = source.len() as f64;
+        let h = perspective(corners, [(0.0, 0.0), (s, 0.0), (0.0, s), (s, s)]).unwrap();
+        let mut noise: u32 = 0x1234_5678;
+        let mut res = vec![vec![0; size]; size];
+        for (y, row) in res.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let (sx, sy) = transform(&h, (x as f64 + 0.5, y as f64 + 0.5));
+                let v = if sx >= 0.0 && sy >= 0.0 && sx < s && sy < s {
+                    source[sy as usize][sx as usize] as f64
+                } else {
+                    255.0
+                };
+                noise ^= noise << 13;
+                noise ^= noise >> 17;
+                noise ^= noise << 5;
+                let t = x as f64 / size as f64;
+                let v = v * (0.4 + 0.45 * t) + 140.0 * t + (noise % 41) as f64 - 20.0;
+                *pixel = v.clamp(0.0, 255.0) as u8;
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_threshold() {
+        let (qr, _) = QrBuilder::new("HELLO WORLD").build().unwrap();
+        let image = photograph(
+            &qr.data,
+            [(0.0, 0.0), (290.0, 0.0), (0.0, 290.0), (290.0, 290.0)],
+            290,
+        );
+        let dark = threshold(&image);
+        // 29 modules with the quiet zone, 10 pixels each
+        let centres = (0..21).flat_map(|row| (0..21).map(move |col| (row, col)));
+        let wrong = |is_dark: &dyn Fn(usize, usize) -> bool| {
+            centres
+                .clone()
+                .filter(|&(row, col)| is_dark(row * 10 + 45, col * 10 + 45) != qr.data[row][col])
+                .count()
+        };
+        // too uneven for one threshold
+        assert!(wrong(&|y, x| image[y][x] < THRESHOLD) > 0);
+        assert_eq!(wrong(&|y, x| dark[y][x]), 0);
+    }
+
+    #[test]
+    fn test_detect() {
+        let cases = [
+            // perspective
+            (
+                "hello from a camera",
+                [(30.0, 40.0), (260.0, 20.0), (20.0, 250.0), (280.0, 290.0)],
+                320,
+            ),
+            // turned a quarter clockwise
+            (
+                "HELLO WORLD",
+                [(270.0, 30.0), (270.0, 270.0), (30.0, 30.0), (30.0, 270.0)],
+                300,
+            ),
+            // turned 45 degrees, the runs along the image axes are longer than a module
+            (
+                "HELLO WORLD",
+                [(150.0, 20.0), (280.0, 150.0), (20.0, 150.0), (150.0, 280.0)],
+                300,
+            ),
+            // tilted, big enough to have alignment and version patterns
+            (
+                "a much longer message that needs a version seven symbol or bigger to fit, so it has \
+                 version information as well as alignment patterns 1234567890",
+                [(100.0, 20.0), (480.0, 90.0), (30.0, 400.0), (440.0, 490.0)],
+                520,
+            ),
+        ];
+        for (text, corners, size) in cases {
+            let (qr, _) = QrBuilder::new(text).ec(ECLevel::Medium).build().unwrap();
+            let grid = detect(&photograph(&qr.data, corners, size)).unwrap();
+            assert_eq!(grid.modules, qr.data, "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_snap_width() {
+        assert_eq!(snap_width(21), Ok(21));
+        assert_eq!(snap_width(23), Ok(25));
+        assert_eq!(snap_width(178), Ok(177));
+        assert_eq!(snap_width(usize::MAX), Err(Error::NoCodeFound));
+        assert_eq!(snap_width(0), Err(Error::NoCodeFound));
+    }
+
+    #[test]
+    fn test_detect_nothing() {
+        assert_eq!(detect(&vec![vec![200; 100]; 100]), Err(Error::NoCodeFound));
+        assert_eq!(image_from_luma(&[0; 10], 3), Err(Error::InvalidImage));
+        assert_eq!(
+            image_from_luma(&[0, 1, 2, 3], 2),
+            Ok(vec![vec![0, 1], vec![2, 3]])
+        );
+    }
+}
@@ -0,0 +1,265 @@
+use clap::ValueEnum;
+
+use crate::layout::{ModuleType, Qr, module_type};
+use crate::render::format_number;
+
+/// How to draw the modules outside the finder patterns. timing and alignment patterns always stay
+/// square so scanners can find them
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, ValueEnum)]
+pub enum ModuleStyle {
+    #[default]
+    Square,
+    /// a dot in each module with a small gap around it
+    Circle,
+    /// squares with rounded corners, not touching their neighbours' corners
+    Rounded,
+    /// dark modules next to each other run together, with the outside corners rounded
+    Connected,
+}
+
+/// How to draw one part of a finder pattern
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, ValueEnum)]
+pub enum EyeStyle {
+    #[default]
+    Square,
+    Rounded,
+    Circle,
+}
+
+/// The shapes used for each part of the symbol
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Style {
+    pub modules: ModuleStyle,
+    /// the 7x7 outer ring of each finder pattern
+    pub eye_ring: EyeStyle,
+    /// the 3x3 square in the middle of each finder pattern
+    pub eye_pupil: EyeStyle,
+}
+
+impl Style {
+    /// plain square modules, which renderers can draw without any of this
+    pub fn is_square(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// a rectangle in modules with the radius of each corner, clockwise from the top left. a corner
+/// radius of half the side makes a circle
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Shape {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+    pub radii: [f64; 4],
+}
+
+impl Shape {
+    fn new(x: f64, y: f64, width: f64, height: f64, radius: f64) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+            radii: [radius; 4],
+        }
+    }
+
+    /// is the point inside, with the right and bottom edges left out so neighbours don't overlap
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let (right, bottom) = (self.x + self.width, self.y + self.height);
+        if x < self.x || y < self.y || x >= right || y >= bottom {
+            return false;
+        }
+        let [tl, tr, br, bl] = self.radii;
+        // the centre of each corner's circle, the point is cut off if it's past that in both
+        // directions and outside the circle
+        let corners = [
+            (self.x + tl, self.y + tl, tl, -1.0, -1.0),
+            (right - tr, self.y + tr, tr, 1.0, -1.0),
+            (right - br, bottom - br, br, 1.0, 1.0),
+            (self.x + bl, bottom - bl, bl, -1.0, 1.0),
+        ];
+        corners.iter().all(|&(cx, cy, r, dx, dy)| {
+            let (ox, oy) = (x - cx, y - cy);
+            !(ox * dx > 0.0 && oy * dy > 0.0 && ox * ox + oy * oy > r * r)
+        })
+    }
+
+    /// the outline as svg path data, moved by `offset` modules
+    pub fn svg_path(&self, offset: f64) -> String {
+        let n = format_number;
+        let (x, y) = (self.x + offset, self.y + offset);
+        let [tl, tr, br, bl] = self.radii;
+        let arc = |r: f64, dx: f64, dy: f64| match r {
+            0.0 => String::new(),
+            _ => format!("a{},{} 0 0 1 {},{}", n(r), n(r), n(dx), n(dy)),
+        };
+        // straight edges are left out where the corners meet
+        let line = |c: char, len: f64| match len {
+            0.0 => String::new(),
+            _ => format!("{}{}", c, n(len)),
+        };
+        let mut res = format!("M{},{}", n(x + tl), n(y));
+        res.push_str(&line('h', self.width - tl - tr));
+        res.push_str(&arc(tr, tr, tr));
+        res.push_str(&line('v', self.height - tr - br));
+        res.push_str(&arc(br, -br, br));
+        res.push_str(&line('h', -(self.width - br - bl)));
+        res.push_str(&arc(bl, -bl, -bl));
+        res.push_str(&line('v', -(self.height - bl - tl)));
+        res.push_str(&arc(tl, tl, -tl));
+        res.push('z');
+        res
+    }
+}
+
+impl EyeStyle {
+    // the corner radius of a square of `size` modules
+    fn radius(self, size: f64) -> f64 {
+        match self {
+            EyeStyle::Square => 0.0,
+            EyeStyle::Rounded => size / 4.0,
+            EyeStyle::Circle => size / 2.0,
+        }
+    }
+}
+
+/// the dark parts of the symbol as shapes in modules from its top left, not counting the quiet
+/// zone. they're meant to be filled even-odd, each finder ring is its outside with a hole shape
+/// inside it and the pupil inside that
+pub(crate) fn shapes(qr: &Qr, style: &Style) -> Vec<Shape> {
+    let width = qr.width();
+    let mut res = vec![];
+
+    let eyes = [(0, 0), (width - 7, 0), (0, width - 7)];
+    for (x, y) in eyes.map(|(x, y)| (x as f64, y as f64)) {
+        let ring = style.eye_ring;
+        res.push(Shape::new(x, y, 7.0, 7.0, ring.radius(7.0)));
+        res.push(Shape::new(x + 1.0, y + 1.0, 5.0, 5.0, ring.radius(5.0)));
+        let pupil = style.eye_pupil.radius(3.0);
+        res.push(Shape::new(x + 2.0, y + 2.0, 3.0, 3.0, pupil));
+    }
+
+    let dark = |row: usize, col: usize| qr.data.get(row).and_then(|r| r.get(col)) == Some(&true);
+    // None for modules drawn already or not at all, otherwise whether they get `style.modules`.
+    // timing and alignment patterns stay square
+    let kind = |row: usize, col: usize| match module_type(qr.version(), (row, col)) {
+        _ if !dark(row, col) => None,
+        Ok(ModuleType::Finder) => None,
+        Ok(ModuleType::Timing | ModuleType::Alignment) => Some(false),
+        _ => Some(style.modules != ModuleStyle::Square),
+    };
+    let dot = |col: usize, row: usize, radius: f64| {
+        Shape::new(col as f64 + 0.05, row as f64 + 0.05, 0.9, 0.9, radius)
+    };
+
+    for row in 0..width {
+        let mut col = 0;
+        while col < width {
+            let Some(styled) = kind(row, col) else {
+                col += 1;
+                continue;
+            };
+            // runs only merge modules drawn the same way
+            let start = col;
+            while col < width && kind(row, col) == Some(styled) {
+                col += 1;
+            }
+            let (x, y, len) = (start as f64, row as f64, (col - start) as f64);
+            match style.modules {
+                _ if !styled => res.push(Shape::new(x, y, len, 1.0, 0.0)),
+                ModuleStyle::Circle => res.extend((start..col).map(|c| dot(c, row, 0.45))),
+                ModuleStyle::Rounded => res.extend((start..col).map(|c| dot(c, row, 0.25))),
+                _ => {
+                    // a corner is only rounded if nothing dark touches it from above or below
+                    let round = |row: Option<usize>, col: usize| match row {
+                        Some(row) if dark(row, col) => 0.0,
+                        _ => 0.5,
+                    };
+                    let (above, below, last) = (row.checked_sub(1), Some(row + 1), col - 1);
+                    res.push(Shape {
+                        radii: [
+                            round(above, start),
+                            round(above, last),
+                            round(below, last),
+                            round(below, start),
+                        ],
+                        ..Shape::new(x, y, len, 1.0, 0.0)
+                    });
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+
+    #[test]
+    fn test_contains() {
+        let circle = Shape::new(0.0, 0.0, 2.0, 2.0, 1.0);
+        assert!(circle.contains(1.0, 1.0));
+        assert!(circle.contains(0.1, 1.0));
+        assert!(!circle.contains(0.2, 0.2));
+        assert!(!circle.contains(2.0, 1.0));
+        let square = Shape::new(1.0, 1.0, 1.0, 1.0, 0.0);
+        assert!(square.contains(1.0, 1.0) && square.contains(1.99, 1.99));
+        assert!(!square.contains(0.99, 1.5));
+    }
+
+    #[test]
+    fn test_svg_path() {
+        assert_eq!(
+            Shape::new(0.0, 0.0, 3.0, 1.0, 0.0).svg_path(4.0),
+            "M4,4h3v1h-3v-1z"
+        );
+        let pill = Shape {
+            radii: [0.5, 0.0, 0.0, 0.5],
+            ..Shape::new(1.0, 2.0, 2.0, 1.0, 0.0)
+        };
+        assert_eq!(
+            pill.svg_path(0.0),
+            "M1.5,2h1.5v1h-1.5a0.5,0.5 0 0 1 -0.5,-0.5a0.5,0.5 0 0 1 0.5,-0.5z"
+        );
+    }
+
+    #[test]
+    fn test_shapes() {
+        let (qr, _) = QrBuilder::new("styled").build().unwrap();
+        // the centre of every module is inside an odd number of shapes if it's dark, whatever
+        // the style. round finders lose their corners but keep the 1:1:3:1:1 lines through the
+        // middle that scanners look for
+        let middle = |i: usize| i == 3 || i == qr.width() - 4;
+        for modules in [
+            ModuleStyle::Square,
+            ModuleStyle::Circle,
+            ModuleStyle::Rounded,
+            ModuleStyle::Connected,
+        ] {
+            for eye in [EyeStyle::Square, EyeStyle::Rounded, EyeStyle::Circle] {
+                let style = Style {
+                    modules,
+                    eye_ring: eye,
+                    eye_pupil: eye,
+                };
+                let shapes = shapes(&qr, &style);
+                for (row, r) in qr.data.iter().enumerate() {
+                    for (col, &m) in r.iter().enumerate() {
+                        let finder =
+                            module_type(qr.version(), (row, col)) == Ok(ModuleType::Finder);
+                        if finder && !middle(row) && !middle(col) {
+                            continue;
+                        }
+                        let (x, y) = (col as f64 + 0.5, row as f64 + 0.5);
+                        let inside = shapes.iter().filter(|s| s.contains(x, y)).count();
+                        assert_eq!(inside % 2 == 1, m, "{:?} at {},{}", style, row, col);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::Write;
+
+use crate::layout::Qr;
+use crate::render::dark_runs;
+use crate::style::{Style, shapes};
+
+/// How to draw an svg
+#[derive(Debug, Clone, PartialEq)]
+pub struct SvgOptions {
+    /// width of a module in px
+    pub module_size: f64,
+    /// width of the blank border in modules, the qr's own if `None`
+    pub quiet_zone: Option<usize>,
+    /// any css colour
+    pub dark: String,
+    /// any css colour, transparent if `None`
+    pub light: Option<String>,
+    /// leave out the width and height so the image fills whatever it's put in
+    pub viewbox_only: bool,
+    /// shapes of the modules and finder patterns
+    pub style: Style,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            module_size: 4.0,
+            quiet_zone: None,
+            dark: String::from("#000000"),
+            light: Some(String::from("#ffffff")),
+            viewbox_only: false,
+            style: Style::default(),
+        }
+    }
+}
+
+/// one path for the background and one for all the dark modules, in units of modules
+pub fn qr_to_svg(qr: &Qr, options: &SvgOptions) -> String {
+    let quiet = options.quiet_zone.unwrap_or(qr.quiet_zone());
+    let size = qr.width() + 2 * quiet;
+
+    let mut res = String::new();
+    write!(
+        res,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {} {}""#,
+        size, size
+    )
+    .unwrap();
+    if !options.viewbox_only {
+        let px = size as f64 * options.module_size;
+        write!(res, r#" width="{}" height="{}""#, px, px).unwrap();
+    }
+    // curves look better anti-aliased
+    if options.style.is_square() {
+        res.push_str(" shape-rendering=\"crispEdges\"");
+    }
+    res.push_str(">\n");
+
+    if let Some(light) = &options.light {
+        writeln!(
+            res,
+            r#"<path fill="{}" d="M0,0h{}v{}H0z"/>"#,
+            light, size, size
+        )
+        .unwrap();
+    }
+
+    // each run of dark modules in a row is one rectangle
+    let mut path = String::new();
+    if options.style.is_square() {
+        for (x, y, len) in dark_runs(&qr.data) {
+            write!(path, "M{},{}h{}v1h-{}z", x + quiet, y + quiet, len, len).unwrap();
+        }
+        writeln!(res, r#"<path fill="{}" d="{}"/>"#, options.dark, path).unwrap();
+    } else {
+        // finder rings have their holes as separate shapes
+        for shape in shapes(qr, &options.style) {
+            path.push_str(&shape.svg_path(quiet as f64));
+        }
+        writeln!(
+            res,
+            r#"<path fill="{}" fill-rule="evenodd" d="{}"/>"#,
+            options.dark, path
+        )
+        .unwrap();
+    }
+    res.push_str("</svg>\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+    use crate::style::{EyeStyle, ModuleStyle};
+
+    // the modules back out of the dark path
+    fn read_path(svg: &str, quiet: usize) -> Vec<Vec<bool>> {
+        let path = svg.lines().find(|l| l.contains("#000000")).unwrap();
+        let d = path.split("d=\"").nth(1).unwrap().trim_end_matches("\"/>");
+        let mut res = vec![];
+        for rect in d.split('z').filter(|r| !r.is_empty()) {
+            let (pos, rest) = rect[1..].split_once('h').unwrap();
+            let (x, y) = pos.split_once(',').unwrap();
+            let (x, y): (usize, usize) = (x.parse().unwrap(), y.parse().unwrap());
+            let len: usize = rest.split('v').next().unwrap().parse().unwrap();
+            res.push((x - quiet, y - quiet, len));
+        }
+        let width = res.iter().map(|r| r.0 + r.2).max().unwrap();
+        let mut modules = vec![vec![false; width]; width];
+        for (x, y, len) in res {
+            modules[y][x..x + len].fill(true);
+        }
+        modules
+    }
+
+    #[test]
+    fn test_qr_to_svg() {
+        let (qr, _) = QrBuilder::new("hello svg").build().unwrap();
+        let svg = qr_to_svg(&qr, &SvgOptions::default());
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 29 29" width="116" height="116""#
+        ));
+        assert_eq!(svg.matches("<path").count(), 2);
+        assert_eq!(read_path(&svg, 4), qr.data);
+    }
+
+    #[test]
+    fn test_svg_options() {
+        let (qr, _) = QrBuilder::new("hello svg").build().unwrap();
+        let svg = qr_to_svg(
+            &qr,
+            &SvgOptions {
+                module_size: 2.5,
+                quiet_zone: Some(1),
+                dark: String::from("#000000"),
+                light: None,
+                viewbox_only: false,
+                style: Style::default(),
+            },
+        );
+        assert!(svg.contains(r#"viewBox="0 0 23 23" width="57.5" height="57.5""#));
+        assert_eq!(svg.matches("<path").count(), 1);
+        assert_eq!(read_path(&svg, 1), qr.data);
+
+        let svg = qr_to_svg(
+            &qr,
+            &SvgOptions {
+                dark: String::from("rebeccapurple"),
+                viewbox_only: true,
+                ..Default::default()
+            },
+        );
+        assert!(!svg.contains("width="));
+        assert!(svg.contains(r#"<path fill="rebeccapurple" d="M4,4h7v1h-7z"#));
+    }
+
+    #[test]
+    fn test_svg_style() {
+        let (qr, _) = QrBuilder::new("hello svg").build().unwrap();
+        let svg = qr_to_svg(
+            &qr,
+            &SvgOptions {
+                style: Style {
+                    modules: ModuleStyle::Connected,
+                    eye_ring: EyeStyle::Rounded,
+                    eye_pupil: EyeStyle::Circle,
+                },
+                ..Default::default()
+            },
+        );
+        assert!(!svg.contains("crispEdges"));
+        // the top left finder's ring, its hole, then its pupil
+        assert!(svg.contains(concat!(
+            r##"<path fill="#000000" fill-rule="evenodd" d=""##,
+            "M5.75,4h3.5a1.75,1.75 0 0 1 1.75,1.75v3.5a1.75,1.75 0 0 1 -1.75,1.75h-3.5",
+            "a1.75,1.75 0 0 1 -1.75,-1.75v-3.5a1.75,1.75 0 0 1 1.75,-1.75z",
+            "M6.25,5h2.5a1.25,1.25 0 0 1 1.25,1.25v2.5a1.25,1.25 0 0 1 -1.25,1.25h-2.5",
+            "a1.25,1.25 0 0 1 -1.25,-1.25v-2.5a1.25,1.25 0 0 1 1.25,-1.25z",
+            "M7.5,6a1.5,1.5 0 0 1 1.5,1.5a1.5,1.5 0 0 1 -1.5,1.5a1.5,1.5 0 0 1 -1.5,-1.5",
+            "a1.5,1.5 0 0 1 1.5,-1.5z"
+        )));
+        assert_eq!(svg.matches("<path").count(), 2);
+    }
+}
@@ -0,0 +1,150 @@
+use clap::ValueEnum;
+
+use crate::layout::Qr;
+
+const RESET: &str = "\x1b[0m";
+
+/// How to draw a qr code as text
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, ValueEnum)]
+pub enum TextStyle {
+    /// two module rows per line with half block characters
+    #[default]
+    HalfBlocks,
+    /// half blocks in black and white, whatever colours the terminal uses
+    Ansi,
+    /// two characters per module for terminals without unicode
+    Ascii,
+}
+
+/// draws the symbol and its quiet zone in characters. the terminal's text colour is used for dark
+/// modules, `invert` swaps that for terminals with light text on a dark background. ansi output
+/// sets its own colours, so it ignores `invert` and is always dark on light
+pub fn qr_to_text(qr: &Qr, style: TextStyle, invert: bool) -> String {
+    let quiet = qr.quiet_zone();
+    let size = qr.width() + 2 * quiet;
+    // modules outside the symbol are light, and so is the row below the bottom one
+    let dark = |row: usize, col: usize| {
+        qr.data
+            .get(row.wrapping_sub(quiet))
+            .and_then(|r| r.get(col.wrapping_sub(quiet)))
+            .is_some_and(|&m| m)
+    };
+    // a negative symbol would need the scanner to invert it too, and ansi doesn't need the help
+    let invert = invert && style != TextStyle::Ansi;
+    let ink = |row: usize, col: usize| row < size && dark(row, col) != invert;
+
+    let mut res = String::new();
+    match style {
+        TextStyle::HalfBlocks => {
+            for row in (0..size).step_by(2) {
+                for col in 0..size {
+                    res.push(match (ink(row, col), ink(row + 1, col)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    });
+                }
+                res.push('\n');
+            }
+        }
+        TextStyle::Ansi => {
+            // an upper half block with the top module as the text colour and the bottom one as
+            // the background. ink is always black here
+            for row in (0..size).step_by(2) {
+                for col in 0..size {
+                    let top = if ink(row, col) { 30 } else { 97 };
+                    let bottom = match ink(row + 1, col) {
+                        _ if row + 1 == size => 49,
+                        true => 40,
+                        false => 107,
+                    };
+                    res.push_str(&format!("\x1b[{};{}m▀", top, bottom));
+                }
+                res.push_str(RESET);
+                res.push('\n');
+            }
+        }
+        TextStyle::Ascii => {
+            for row in 0..size {
+                for col in 0..size {
+                    res.push_str(if ink(row, col) { "##" } else { "  " });
+                }
+                res.push('\n');
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::QrBuilder;
+
+    // the module grid back out of the text, with the quiet zone
+    fn read_text(text: &str, style: TextStyle) -> Vec<Vec<bool>> {
+        let mut res = vec![];
+        for line in text.lines() {
+            match style {
+                TextStyle::HalfBlocks => {
+                    res.push(line.chars().map(|c| matches!(c, '█' | '▀')).collect());
+                    res.push(line.chars().map(|c| matches!(c, '█' | '▄')).collect());
+                }
+                TextStyle::Ansi => {
+                    let cells: Vec<&str> = line.split("\x1b[").skip(1).collect();
+                    let cells = &cells[..cells.len() - 1];
+                    res.push(cells.iter().map(|c| c.starts_with("30;")).collect());
+                    res.push(cells.iter().map(|c| c.contains(";40m")).collect());
+                }
+                TextStyle::Ascii => {
+                    res.push(line.as_bytes().chunks(2).map(|c| c == b"##").collect())
+                }
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_qr_to_text() {
+        let (qr, _) = QrBuilder::new("hello").quiet_zone(1).build().unwrap();
+        // one module of quiet zone around each side
+        let mut expected = vec![vec![false; 23]; 23];
+        for (row, modules) in qr.data.iter().enumerate() {
+            expected[row + 1][1..22].copy_from_slice(modules);
+        }
+        let inverted: Vec<Vec<bool>> = expected
+            .iter()
+            .map(|r| r.iter().map(|m| !m).collect())
+            .collect();
+
+        for style in [TextStyle::HalfBlocks, TextStyle::Ansi, TextStyle::Ascii] {
+            let text = qr_to_text(&qr, style, false);
+            let lines = match style {
+                TextStyle::Ascii => 23,
+                _ => 12,
+            };
+            assert_eq!(text.lines().count(), lines);
+            // odd number of rows so the last line's bottom half is blank
+            let read = read_text(&text, style);
+            assert_eq!(read[..23], expected);
+            let flipped = match style {
+                TextStyle::Ansi => &expected,
+                _ => &inverted,
+            };
+            assert_eq!(
+                read_text(&qr_to_text(&qr, style, true), style)[..23],
+                flipped[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_ansi_reset() {
+        let (qr, _) = QrBuilder::new("hello").build().unwrap();
+        let text = qr_to_text(&qr, TextStyle::Ansi, false);
+        assert!(text.lines().all(|l| l.ends_with(RESET)));
+        // the last line's bottom half is the terminal's own background
+        assert!(text.lines().last().unwrap().contains(";49m"));
+    }
+}